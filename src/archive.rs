@@ -0,0 +1,362 @@
+use crate::checksums::{self, Expected};
+use flate2::read::GzDecoder;
+use std::{
+  fs::{self, File},
+  io::{self, Read},
+  path::{Component, Path, PathBuf},
+};
+use zed_extension_api::{self as zed, DownloadedFileType};
+
+// Where the raw asset goes until it's extracted.
+const DOWNLOAD_PATH: &str = ".mpls-download";
+
+// Starts the error for an asset that came down without any data. It's retried, since that's the
+// server having a bad moment rather than the asset being broken.
+const EMPTY: &str = "The downloaded archive was empty";
+
+// Worth its own message: extracting an empty body fails with something about corrupt archives.
+pub(crate) fn check_not_empty(path: &str) -> zed::Result<()> {
+  match fs::metadata(path) {
+    Ok(metadata) if metadata.len() == 0 => Err(format!(
+      "{}. The server sent no data, try again later.",
+      EMPTY
+    )),
+    _ => Ok(()),
+  }
+}
+
+// Enough to see the tar magic at offset 257.
+const HEADER_LEN: usize = 512;
+
+// What an asset actually is, going by its first bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+  Zip,
+  Gzip,
+  // A gzip whose decompressed first bytes are a tar header.
+  GzipTar,
+  Tar,
+  Executable,
+}
+
+fn sniff(header: &[u8]) -> Option<Format> {
+  if header.starts_with(b"PK\x03\x04") {
+    Some(Format::Zip)
+  } else if header.starts_with(&[0x1f, 0x8b]) {
+    Some(Format::Gzip)
+  } else if header.get(257..262) == Some(b"ustar") {
+    Some(Format::Tar)
+  } else if header.starts_with(b"\x7fELF")
+    || header.starts_with(b"MZ")
+    || [
+      [0xcf, 0xfa, 0xed, 0xfe],
+      [0xfe, 0xed, 0xfa, 0xcf],
+      [0xca, 0xfe, 0xba, 0xbe],
+    ]
+    .iter()
+    .any(|magic| header.starts_with(magic))
+  {
+    Some(Format::Executable)
+  } else {
+    None
+  }
+}
+
+// Downloads the asset once, hashing it on the way, and extracts exactly the bytes that were checked
+// against `digest`. Upstream may have labelled the asset wrong, e.g. a plain gzip or zip named
+// .tar.gz, so it's extracted as what its first bytes say it is.
+pub(crate) fn install(
+  url: &str,
+  labelled: DownloadedFileType,
+  dir_name: &str,
+  executable_path: &str,
+  digest: Option<&Expected>,
+  user_agent: Option<&str>,
+) -> zed::Result<()> {
+  let result = checksums::download(url, DOWNLOAD_PATH, user_agent).and_then(|actual| {
+    // An empty body would otherwise look like a mismatch or a broken archive, neither of which is
+    // retried the same way.
+    check_not_empty(DOWNLOAD_PATH)?;
+    if let Some(digest) = digest {
+      digest.check_digest(&actual)?;
+    }
+    let format = extract(DOWNLOAD_PATH, dir_name, executable_path)?;
+    if !matches!(
+      (format, labelled),
+      (Format::Zip, DownloadedFileType::Zip) | (Format::GzipTar, DownloadedFileType::GzipTar)
+    ) {
+      log!(
+        "{} is labelled {:?}, but it was {:?}",
+        url,
+        labelled,
+        format
+      );
+    }
+    Ok(())
+  });
+  let _ = fs::remove_file(DOWNLOAD_PATH);
+  result
+}
+
+// Extracts the asset at `path` into `dir_name`. Plain gzip and raw executables are a single file,
+// which goes where the binary is expected.
+fn extract(path: &str, dir_name: &str, executable_path: &str) -> zed::Result<Format> {
+  let open = || File::open(path).map_err(|err| format!("Can't read the downloaded asset: {}", err));
+  let format = match sniff(&header(open()?)) {
+    // A gzip that isn't a tarball is most likely the gzipped binary itself.
+    Some(Format::Gzip) => match sniff(&header(GzDecoder::new(open()?))) {
+      Some(Format::Tar) => Format::GzipTar,
+      _ => Format::Gzip,
+    },
+    Some(format) => format,
+    None => {
+      return Err(
+        "The asset isn't a zip, gzip, tar or executable, so it can't be installed.".to_string(),
+      );
+    }
+  };
+  let extracted = match format {
+    Format::Zip => extract_zip(open()?, dir_name),
+    Format::GzipTar => extract_tar(GzDecoder::new(open()?), dir_name),
+    Format::Tar => extract_tar(open()?, dir_name),
+    Format::Gzip => write_file(&mut GzDecoder::new(open()?), Path::new(executable_path)),
+    Format::Executable => {
+      create_parent(Path::new(executable_path)).and_then(|()| fs::rename(path, executable_path))
+    }
+  };
+  extracted
+    .map(|()| format)
+    .map_err(|err| format!("Extracting the {:?} asset failed: {}", format, err))
+}
+
+fn header(reader: impl Read) -> Vec<u8> {
+  let mut header = Vec::with_capacity(HEADER_LEN);
+  // Whatever could be read is enough to sniff, and a broken archive fails to extract anyway.
+  let _ = reader.take(HEADER_LEN as u64).read_to_end(&mut header);
+  header
+}
+
+// Entries are written one by one: the tar crate's unpack() fails on WASI, since it can't set
+// permissions there. make_executable() sets the executable bit afterwards.
+fn extract_tar(reader: impl Read, dir_name: &str) -> io::Result<()> {
+  let mut archive = tar::Archive::new(reader);
+  for entry in archive.entries()? {
+    let mut entry = entry?;
+    let destination = destination(dir_name, &entry.path()?)?;
+    match entry.header().entry_type() {
+      tar::EntryType::Directory => fs::create_dir_all(&destination)?,
+      tar::EntryType::Regular | tar::EntryType::Continuous => write_file(&mut entry, &destination)?,
+      // Links, devices and the like have no place in a release archive.
+      _ => {}
+    }
+  }
+  Ok(())
+}
+
+fn extract_zip(file: File, dir_name: &str) -> io::Result<()> {
+  let mut archive = zip::ZipArchive::new(file)?;
+  for index in 0..archive.len() {
+    let mut entry = archive.by_index(index)?;
+    let name = entry.name()?.into_owned();
+    let destination = destination(dir_name, Path::new(&name))?;
+    if entry.is_dir() {
+      fs::create_dir_all(&destination)?;
+    } else {
+      write_file(&mut entry, &destination)?;
+    }
+  }
+  Ok(())
+}
+
+// Where an archive entry goes inside `dir_name`. An entry that would end up outside of it, through
+// an absolute path or `..`, fails the whole extraction.
+fn destination(dir_name: &str, entry_path: &Path) -> io::Result<PathBuf> {
+  let mut destination = PathBuf::from(dir_name);
+  for component in entry_path.components() {
+    match component {
+      Component::Normal(part) => destination.push(part),
+      Component::CurDir => {}
+      _ => {
+        return Err(io::Error::new(
+          io::ErrorKind::InvalidData,
+          format!("{} points outside of the archive", entry_path.display()),
+        ));
+      }
+    }
+  }
+  Ok(destination)
+}
+
+fn write_file(reader: &mut impl Read, destination: &Path) -> io::Result<()> {
+  create_parent(destination)?;
+  io::copy(reader, &mut File::create(destination)?).map(|_| ())
+}
+
+fn create_parent(path: &Path) -> io::Result<()> {
+  match path.parent() {
+    Some(parent) if !parent.as_os_str().is_empty() => fs::create_dir_all(parent),
+    _ => Ok(()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn sniffs_archives() {
+    assert!(matches!(sniff(b"PK\x03\x04rest"), Some(Format::Zip)));
+    assert!(matches!(
+      sniff(&[0x1f, 0x8b, 0x08, 0x00]),
+      Some(Format::Gzip)
+    ));
+    let mut tar = vec![0; HEADER_LEN];
+    tar[257..262].copy_from_slice(b"ustar");
+    assert!(matches!(sniff(&tar), Some(Format::Tar)));
+  }
+
+  #[test]
+  fn sniffs_executables() {
+    assert!(matches!(
+      sniff(b"\x7fELF\x02\x01"),
+      Some(Format::Executable)
+    ));
+    assert!(matches!(sniff(b"MZ\x90\x00"), Some(Format::Executable)));
+    assert!(matches!(
+      sniff(&[0xcf, 0xfa, 0xed, 0xfe, 0x0c]),
+      Some(Format::Executable)
+    ));
+  }
+
+  #[test]
+  fn rejects_anything_else() {
+    assert!(sniff(b"").is_none());
+    assert!(sniff(b"<html>Not Found</html>").is_none());
+    // Too short to hold the tar magic.
+    assert!(sniff(&[0; 100]).is_none());
+  }
+
+  #[test]
+  fn rejects_empty_downloads() {
+    let path = std::env::temp_dir().join("mpls-archive-empty");
+    let path = path.to_str().unwrap();
+    fs::write(path, "").unwrap();
+    let err = check_not_empty(path).unwrap_err();
+    assert!(err.starts_with(EMPTY), "{}", err);
+    fs::write(path, [0x1f, 0x8b]).unwrap();
+    assert!(check_not_empty(path).is_ok());
+    let _ = fs::remove_file(path);
+    // A missing file is left to whatever reads it next.
+    assert!(check_not_empty(path).is_ok());
+  }
+
+  // A scratch directory holding the asset at `asset` and the install directory, cleaned first.
+  fn scratch(name: &str, asset: &[u8]) -> (PathBuf, String, String) {
+    let root = std::env::temp_dir().join(name);
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    let path = root.join("asset");
+    fs::write(&path, asset).unwrap();
+    let dir_name = root.join("mpls_0.16.0_linux_amd64");
+    let executable_path = dir_name.join("mpls");
+    (
+      path,
+      dir_name.to_str().unwrap().to_string(),
+      executable_path.to_str().unwrap().to_string(),
+    )
+  }
+
+  fn tar(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+    for (path, contents) in files {
+      let mut header = tar::Header::new_gnu();
+      header.set_size(contents.len() as u64);
+      header.set_mode(0o755);
+      header.set_cksum();
+      builder.append_data(&mut header, path, *contents).unwrap();
+    }
+    builder.into_inner().unwrap()
+  }
+
+  fn gzip(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    io::Write::write_all(&mut encoder, bytes).unwrap();
+    encoder.finish().unwrap()
+  }
+
+  #[test]
+  fn extracts_tarballs() {
+    let tarball = tar(&[("mpls", b"\x7fELF binary"), ("docs/LICENSE", b"MIT")]);
+    for (name, asset, expected) in [
+      ("mpls-archive-tar-gz", gzip(&tarball), Format::GzipTar),
+      ("mpls-archive-tar", tarball.clone(), Format::Tar),
+    ] {
+      let (path, dir_name, executable_path) = scratch(name, &asset);
+      let format = extract(path.to_str().unwrap(), &dir_name, &executable_path).unwrap();
+      assert_eq!(format, expected);
+      assert_eq!(fs::read(&executable_path).unwrap(), b"\x7fELF binary");
+      assert_eq!(
+        fs::read(Path::new(&dir_name).join("docs/LICENSE")).unwrap(),
+        b"MIT"
+      );
+      let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+  }
+
+  #[test]
+  fn extracts_zips() {
+    let mut zip = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+    let options =
+      zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    zip.add_directory("bin/", options).unwrap();
+    zip.start_file("bin/mpls.exe", options).unwrap();
+    io::Write::write_all(&mut zip, b"MZ binary").unwrap();
+    let asset = zip.finish().unwrap().into_inner();
+
+    let (path, dir_name, executable_path) = scratch("mpls-archive-zip", &asset);
+    let format = extract(path.to_str().unwrap(), &dir_name, &executable_path).unwrap();
+    assert_eq!(format, Format::Zip);
+    assert_eq!(
+      fs::read(Path::new(&dir_name).join("bin/mpls.exe")).unwrap(),
+      b"MZ binary"
+    );
+    let _ = fs::remove_dir_all(path.parent().unwrap());
+  }
+
+  #[test]
+  fn installs_single_files_as_the_binary() {
+    for (name, asset, expected) in [
+      ("mpls-archive-gz", gzip(b"\x7fELF binary"), Format::Gzip),
+      (
+        "mpls-archive-raw",
+        b"\x7fELF binary".to_vec(),
+        Format::Executable,
+      ),
+    ] {
+      let (path, dir_name, executable_path) = scratch(name, &asset);
+      let format = extract(path.to_str().unwrap(), &dir_name, &executable_path).unwrap();
+      assert_eq!(format, expected);
+      assert_eq!(fs::read(&executable_path).unwrap(), b"\x7fELF binary");
+      let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    let (path, dir_name, executable_path) = scratch("mpls-archive-html", b"<html>Not Found</html>");
+    assert!(extract(path.to_str().unwrap(), &dir_name, &executable_path).is_err());
+    let _ = fs::remove_dir_all(path.parent().unwrap());
+  }
+
+  #[test]
+  fn keeps_entries_inside_the_install_directory() {
+    assert_eq!(
+      destination("mpls_0.16.0_linux_amd64", Path::new("./bin/mpls")).unwrap(),
+      Path::new("mpls_0.16.0_linux_amd64/bin/mpls")
+    );
+    for entry_path in ["../mpls", "bin/../../mpls", "/usr/bin/mpls"] {
+      assert!(
+        destination("mpls_0.16.0_linux_amd64", Path::new(entry_path)).is_err(),
+        "{}",
+        entry_path
+      );
+    }
+  }
+}
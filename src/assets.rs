@@ -0,0 +1,232 @@
+use crate::{MplsSettings, naming::AssetNaming};
+use zed_extension_api::{DownloadedFileType, GithubRelease, GithubReleaseAsset};
+
+// Releases are often published a few minutes before CI attaches the binaries. That deserves its
+// own message rather than a "can't find" listing no assets.
+pub(crate) fn not_uploaded_yet(release: &GithubRelease, settings: &MplsSettings) -> Option<String> {
+  if !release.assets.is_empty() {
+    return None;
+  }
+  Some(format!(
+    "MPLS release {} was published, but its binaries haven't been uploaded yet. Try again in a few minutes{}.",
+    release.version,
+    if settings.require_assets {
+      ""
+    } else {
+      ", or set require_assets to true to use the newest release that has them"
+    }
+  ))
+}
+
+// Looks for the asset in each of naming.archive_formats() in turn, returning the first one the
+// release has, with how to extract it. Err holds every name that was tried.
+pub(crate) fn find_preferred_asset<'a>(
+  assets: &'a [GithubReleaseAsset],
+  naming: &AssetNaming,
+  version: &str,
+  settings: &MplsSettings,
+) -> Result<(&'a GithubReleaseAsset, DownloadedFileType), Vec<String>> {
+  let mut tried = Vec::new();
+  for (ext, file_type) in naming.archive_formats() {
+    let archived_asset_name = naming.archived(version, ext);
+    if let Some(asset) = find_asset(
+      assets,
+      &archived_asset_name,
+      settings.asset_scan_limit,
+      &settings.asset_extensions,
+    ) {
+      return Ok((asset, file_type));
+    }
+    tried.push(archived_asset_name);
+  }
+  Err(tried)
+}
+
+// Picks the release asset named `archived_asset_name` among the first `scan_limit` assets
+// that end in one of `extensions`, so that checksums or signatures are never mistaken for the archive.
+// The exact name wins; otherwise we accept a name that only differs in casing,
+// so small naming wobbles upstream (e.g. `.TAR.GZ`) don't break installs.
+// When several names qualify, the one closest in casing wins, then the lowest in byte order,
+// so the pick never depends on the order GitHub lists assets in.
+fn find_asset<'a>(
+  assets: &'a [GithubReleaseAsset],
+  archived_asset_name: &str,
+  scan_limit: usize,
+  extensions: &[String],
+) -> Option<&'a GithubReleaseAsset> {
+  if assets.len() > scan_limit {
+    log!(
+      "The release has {} assets, only looking at the first {}",
+      assets.len(),
+      scan_limit
+    );
+  }
+  let assets: Vec<&GithubReleaseAsset> = assets[..assets.len().min(scan_limit)]
+    .iter()
+    .filter(|asset| {
+      let name = asset.name.to_ascii_lowercase();
+      extensions
+        .iter()
+        .any(|extension| name.ends_with(&extension.to_ascii_lowercase()))
+    })
+    .collect();
+  if let Some(asset) = assets
+    .iter()
+    .find(|asset| asset.name == archived_asset_name)
+  {
+    return Some(asset);
+  }
+
+  let mut candidates: Vec<&GithubReleaseAsset> = assets
+    .into_iter()
+    .filter(|asset| asset.name.eq_ignore_ascii_case(archived_asset_name))
+    .collect();
+  candidates.sort_by(|a, b| {
+    casing_differences(&a.name, archived_asset_name)
+      .cmp(&casing_differences(&b.name, archived_asset_name))
+      .then_with(|| a.name.cmp(&b.name))
+  });
+  if candidates.len() > 1 {
+    let names: Vec<&str> = candidates.iter().map(|asset| asset.name.as_str()).collect();
+    log!(
+      "Several assets match {}: {}. Picking {}",
+      archived_asset_name,
+      names.join(", "),
+      names[0]
+    );
+  }
+  candidates.first().copied()
+}
+
+// How many characters differ only in casing. The names are equal ignoring case.
+fn casing_differences(name: &str, archived_asset_name: &str) -> usize {
+  name
+    .bytes()
+    .zip(archived_asset_name.bytes())
+    .filter(|(a, b)| a != b)
+    .count()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const ARCHIVE: &str = "mpls_0.16.0_linux_amd64.tar.gz";
+
+  fn assets(names: &[&str]) -> Vec<GithubReleaseAsset> {
+    names
+      .iter()
+      .map(|name| GithubReleaseAsset {
+        name: name.to_string(),
+        download_url: format!("https://example.com/{}", name),
+      })
+      .collect()
+  }
+
+  fn pick(names: &[&str]) -> Option<String> {
+    let extensions = MplsSettings::default().asset_extensions;
+    find_asset(&assets(names), ARCHIVE, 100, &extensions).map(|asset| asset.name.clone())
+  }
+
+  #[test]
+  fn tolerates_casing_variants() {
+    assert_eq!(
+      pick(&["mpls_0.16.0_linux_amd64.TAR.GZ"]).as_deref(),
+      Some("mpls_0.16.0_linux_amd64.TAR.GZ")
+    );
+    assert_eq!(
+      pick(&["MPLS_0.16.0_Linux_AMD64.tar.gz"]).as_deref(),
+      Some("MPLS_0.16.0_Linux_AMD64.tar.gz")
+    );
+    // The one closest in casing, whatever order they're listed in.
+    for names in [
+      [
+        "MPLS_0.16.0_LINUX_AMD64.TAR.GZ",
+        "mpls_0.16.0_linux_amd64.TAR.GZ",
+      ],
+      [
+        "mpls_0.16.0_linux_amd64.TAR.GZ",
+        "MPLS_0.16.0_LINUX_AMD64.TAR.GZ",
+      ],
+    ] {
+      assert_eq!(
+        pick(&names).as_deref(),
+        Some("mpls_0.16.0_linux_amd64.TAR.GZ")
+      );
+    }
+  }
+
+  #[test]
+  fn prefers_the_exact_name() {
+    assert_eq!(
+      pick(&[
+        "mpls_0.16.0_linux_amd64.TAR.GZ",
+        "mpls_0.16.0_linux_amd64.tar.gz.1",
+        ARCHIVE
+      ])
+      .as_deref(),
+      Some(ARCHIVE)
+    );
+    assert_eq!(pick(&["mpls_0.16.0_linux_amd64.tar.gz.1"]), None);
+    assert_eq!(pick(&["mpls_0.16.0_linux_arm64.tar.gz"]), None);
+  }
+
+  #[test]
+  fn never_picks_non_archives() {
+    let names = [
+      "mpls_0.16.0_checksums.txt",
+      "mpls_0.16.0_linux_amd64.tar.gz.sig",
+      "mpls_0.16.0_linux_amd64.tar.gz.sbom",
+      "mpls_0.16.0_linux_amd64.tar.gz.sha256",
+      "mpls_0.16.0_linux_amd64.txt",
+      ARCHIVE,
+      "mpls_0.16.0_linux_amd64.tar.gz.pem",
+    ];
+    assert_eq!(pick(&names).as_deref(), Some(ARCHIVE));
+    assert_eq!(pick(&names[..5]), None);
+
+    // Unless the allowlist is widened for them.
+    let extensions = vec![".sig".to_string()];
+    let assets = assets(&names);
+    let asset = find_asset(
+      &assets,
+      "mpls_0.16.0_linux_amd64.tar.gz.sig",
+      100,
+      &extensions,
+    );
+    assert_eq!(
+      asset.map(|asset| asset.name.as_str()),
+      Some("mpls_0.16.0_linux_amd64.tar.gz.sig")
+    );
+  }
+
+  #[test]
+  fn only_scans_up_to_the_limit() {
+    let extensions = MplsSettings::default().asset_extensions;
+    let assets = assets(&["mpls_0.16.0_darwin_arm64.tar.gz", ARCHIVE]);
+    assert!(find_asset(&assets, ARCHIVE, 1, &extensions).is_none());
+    assert!(find_asset(&assets, ARCHIVE, 2, &extensions).is_some());
+  }
+
+  #[test]
+  fn explains_releases_without_assets() {
+    let release = GithubRelease {
+      version: "v0.17.0".to_string(),
+      assets: Vec::new(),
+    };
+    let mut settings = MplsSettings::default();
+    let err = not_uploaded_yet(&release, &settings).unwrap();
+    assert!(err.contains("v0.17.0"), "{}", err);
+    assert!(!err.contains("require_assets"), "{}", err);
+
+    settings.require_assets = false;
+    let err = not_uploaded_yet(&release, &settings).unwrap();
+    assert!(err.contains("set require_assets to true"), "{}", err);
+
+    let release = GithubRelease {
+      version: "v0.16.0".to_string(),
+      assets: assets(&[ARCHIVE]),
+    };
+    assert_eq!(not_uploaded_yet(&release, &settings), None);
+  }
+}
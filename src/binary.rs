@@ -0,0 +1,270 @@
+use crate::version::VERSION_PATTERN;
+use regex::Regex;
+use std::{env::current_dir, fs::File, io::Read};
+use zed_extension_api as zed;
+
+// Enough to cover the ELF and Mach-O headers and, in practice, the PE header.
+const HEADER_LEN: usize = 4096;
+
+// Fails when the binary at `path` is obviously built for another CPU than the host and the OS can't
+// emulate it, so that users see why instead of "server exited immediately".
+// Binaries we can't read (e.g. outside the extension's sandbox) or don't recognize are accepted.
+pub(crate) fn check_arch(path: &str) -> zed::Result<()> {
+  let (os, arch) = zed::current_platform();
+  let host_arch = match arch {
+    zed::Architecture::Aarch64 => "arm64",
+    zed::Architecture::X86 => "386",
+    zed::Architecture::X8664 => "amd64",
+  };
+
+  let mut header = Vec::with_capacity(HEADER_LEN);
+  let Ok(file) = File::open(path) else {
+    return Ok(());
+  };
+  if file
+    .take(HEADER_LEN as u64)
+    .read_to_end(&mut header)
+    .is_err()
+  {
+    return Ok(());
+  }
+
+  match detect_arch(&header) {
+    Some(binary_arch) if binary_arch != host_arch && runs_emulated(os, host_arch, binary_arch) => {
+      log!(
+        "{} is built for {} and runs emulated on this {} machine. The {} build of mpls would be faster.",
+        path,
+        binary_arch,
+        host_arch,
+        host_arch
+      );
+      Ok(())
+    }
+    Some(binary_arch) if binary_arch != host_arch => Err(format!(
+      "{} is built for {}, but this machine is {}. Install the {} build of mpls instead.",
+      path, binary_arch, host_arch, host_arch
+    )),
+    _ => Ok(()),
+  }
+}
+
+// Whether `os` runs binaries for `binary_arch` on a `host_arch` CPU: Rosetta on Apple silicon,
+// and the x86 emulation and WOW64 on Windows.
+fn runs_emulated(os: zed::Os, host_arch: &str, binary_arch: &str) -> bool {
+  matches!(
+    (os, host_arch, binary_arch),
+    (zed::Os::Mac, "arm64", "amd64")
+      | (zed::Os::Windows, "arm64", "amd64" | "386")
+      | (zed::Os::Windows, "amd64", "386")
+  )
+}
+
+// Runs `<binary> --version`, so that a download that can't even start is caught before it's launched.
+// Only a binary that ran and failed counts. Not being able to run anything at all, e.g. without the
+// process:exec capability, lets it through.
+pub(crate) fn check_runs(executable_path: &str) -> zed::Result<()> {
+  let path = host_path(executable_path)?;
+  match zed::process::Command::new(&path).arg("--version").output() {
+    Ok(output) if output.status == Some(0) => {
+      log!(
+        "{} --version: {}",
+        path,
+        String::from_utf8_lossy(&output.stdout).trim_end()
+      );
+      Ok(())
+    }
+    Ok(output) => Err(format!(
+      "{} --version exited with {:?}: {}",
+      path,
+      output.status,
+      String::from_utf8_lossy(&output.stderr).trim_end()
+    )),
+    Err(err) => {
+      log!("Can't run {} to check it: {}", path, err);
+      Ok(())
+    }
+  }
+}
+
+// Whether running a binary failed because there's nothing runnable at its path: ENOENT, EACCES or
+// ENOEXEC, and their Windows counterparts. Anything else, e.g. a missing process:exec capability,
+// says nothing about the binary itself.
+pub(crate) fn is_not_runnable(err: &str) -> bool {
+  let err = err.to_ascii_lowercase();
+  [
+    "no such file",
+    "not found",
+    "cannot find",
+    "(os error 2)",
+    "(os error 3)",
+    "permission denied",
+    "access is denied",
+    "(os error 13)",
+    "(os error 5)",
+    "exec format error",
+    "(os error 8)",
+    "not a valid win32 application",
+    "(os error 193)",
+  ]
+  .iter()
+  .any(|needle| err.contains(needle))
+}
+
+// zed::make_file_executable, minus Windows, where there is no executable bit to set
+// and an error could only fail the install for nothing.
+pub(crate) fn make_executable(path: &str) -> zed::Result<()> {
+  make_executable_on(zed::current_platform().0, path, zed::make_file_executable)
+}
+
+fn make_executable_on(
+  os: zed::Os,
+  path: &str,
+  make_file_executable: impl FnOnce(&str) -> zed::Result<()>,
+) -> zed::Result<()> {
+  if matches!(os, zed::Os::Windows) {
+    return Ok(());
+  }
+  make_file_executable(path).map_err(|err| {
+    format!(
+      "Can't make {} executable: {}. Check the permissions of the extension's work directory.",
+      path, err
+    )
+  })
+}
+
+// The version a binary reports with --version, e.g. 0.16.0 from "mpls version v0.16.0".
+// None when it can't be run or doesn't print anything that looks like a version.
+pub(crate) fn reported_version(path: &str) -> Option<String> {
+  let output = zed::process::Command::new(path)
+    .arg("--version")
+    .output()
+    .ok()?;
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  Regex::new(VERSION_PATTERN)
+    .ok()?
+    .find(&stdout)
+    .map(|version| version.as_str().to_string())
+}
+
+// The absolute path of something in the work directory, for handing to processes on the host.
+pub(crate) fn host_path(path: &str) -> zed::Result<String> {
+  Ok(
+    current_dir()
+      .map_err(|err| err.to_string())?
+      .join(path)
+      .display()
+      .to_string(),
+  )
+}
+
+// Path::is_absolute() follows the sandbox's Unix rules, but these paths come from the host,
+// which may be Windows.
+pub(crate) fn is_absolute_host_path(path: &str) -> bool {
+  let bytes = path.as_bytes();
+  let is_drive_path = bytes.len() >= 3
+    && bytes[0].is_ascii_alphabetic()
+    && bytes[1] == b':'
+    && matches!(bytes[2], b'/' | b'\\');
+  path.starts_with('/') || path.starts_with("\\\\") || is_drive_path
+}
+
+fn detect_arch(header: &[u8]) -> Option<&'static str> {
+  let u16_le = |offset: usize| {
+    Some(u16::from_le_bytes(
+      header.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+  };
+  let u32_le = |offset: usize| {
+    Some(u32::from_le_bytes(
+      header.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+  };
+
+  match header.get(..4)? {
+    // ELF. e_machine is at offset 18 and little endian on every platform MPLS supports.
+    [0x7f, b'E', b'L', b'F'] => match u16_le(18)? {
+      0x03 => Some("386"),
+      0x3e => Some("amd64"),
+      0xb7 => Some("arm64"),
+      _ => None,
+    },
+    // 64-bit little endian Mach-O. Universal binaries run everywhere, so they aren't matched.
+    [0xcf, 0xfa, 0xed, 0xfe] => match u32_le(4)? {
+      0x0100_0007 => Some("amd64"),
+      0x0100_000c => Some("arm64"),
+      _ => None,
+    },
+    // PE. The offset of the PE header is at 0x3c, and the machine type follows the signature.
+    [b'M', b'Z', ..] => {
+      let pe_offset = u32_le(0x3c)? as usize;
+      if header.get(pe_offset..pe_offset + 4)? != b"PE\0\0" {
+        return None;
+      }
+      match u16_le(pe_offset + 4)? {
+        0x014c => Some("386"),
+        0x8664 => Some("amd64"),
+        0xaa64 => Some("arm64"),
+        _ => None,
+      }
+    }
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn only_needs_the_executable_bit_off_windows() {
+    let path = "mpls_0.16.0_windows_amd64/mpls.exe";
+    assert!(
+      make_executable_on(zed::Os::Windows, path, |_| {
+        Err("operation not supported".to_string())
+      })
+      .is_ok()
+    );
+
+    let path = "mpls_0.16.0_linux_amd64/mpls";
+    assert!(make_executable_on(zed::Os::Linux, path, |_| Ok(())).is_ok());
+    let err = make_executable_on(zed::Os::Linux, path, |_| {
+      Err("Permission denied (os error 13)".to_string())
+    })
+    .unwrap_err();
+    assert!(
+      err.starts_with("Can't make mpls_0.16.0_linux_amd64/mpls executable: Permission denied")
+    );
+    assert!(err.contains("work directory"), "{}", err);
+  }
+
+  #[test]
+  fn tells_missing_binaries_from_other_failures() {
+    for err in [
+      "No such file or directory (os error 2)",
+      "The system cannot find the file specified. (os error 2)",
+      "Permission denied (os error 13)",
+      "Access is denied. (os error 5)",
+      "Exec format error (os error 8)",
+      "%1 is not a valid Win32 application. (os error 193)",
+    ] {
+      assert!(is_not_runnable(err), "{}", err);
+    }
+    for err in [
+      "capability for process:exec mpls --version is not granted by the extension host",
+      "Resource temporarily unavailable (os error 11)",
+      "No space left on device (os error 28)",
+    ] {
+      assert!(!is_not_runnable(err), "{}", err);
+    }
+  }
+
+  #[test]
+  fn accepts_binaries_the_os_emulates() {
+    assert!(runs_emulated(zed::Os::Mac, "arm64", "amd64"));
+    assert!(runs_emulated(zed::Os::Windows, "arm64", "amd64"));
+    assert!(runs_emulated(zed::Os::Windows, "amd64", "386"));
+    assert!(!runs_emulated(zed::Os::Mac, "amd64", "arm64"));
+    assert!(!runs_emulated(zed::Os::Linux, "arm64", "amd64"));
+    assert!(!runs_emulated(zed::Os::Windows, "amd64", "arm64"));
+  }
+}
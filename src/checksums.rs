@@ -0,0 +1,250 @@
+use crate::{MPLS_REPO, release};
+use sha2::{Digest, Sha256};
+use std::{
+  fs::{self, File},
+  io::Write,
+};
+use zed_extension_api::{
+  self as zed, DownloadedFileType, GithubRelease, GithubReleaseAsset, serde_json,
+};
+
+// Where the checksums file goes while it's read.
+const VERIFY_PATH: &str = ".mpls-download-verify";
+
+// The source of a digest given with the asset_sha256 setting.
+const PINNED: &str = "asset_sha256";
+
+// The source of a digest from the release's REST API metadata.
+const API_DIGEST: &str = "GitHub's release metadata";
+
+// Starts every mismatch error, so that it can be told apart from a failed download.
+const MISMATCH: &str = "Checksum mismatch";
+
+// A mismatch isn't retried, and isn't papered over by falling back to an installed version.
+pub(crate) fn is_mismatch(err: &str) -> bool {
+  err.starts_with(MISMATCH)
+}
+
+// The SHA-256 an asset has to have, and where that came from, for messages.
+pub(crate) struct Expected {
+  asset_name: String,
+  digest: String,
+  source: String,
+}
+
+impl Expected {
+  pub(crate) fn digest(&self) -> &str {
+    &self.digest
+  }
+
+  // Checks the SHA-256 of the raw asset, as download() returns it.
+  pub(crate) fn check_digest(&self, actual: &str) -> zed::Result<()> {
+    if actual != self.digest {
+      return Err(format!(
+        "{}: {} has SHA-256 {}, but {} says {}. Not installing it.{}",
+        MISMATCH,
+        self.asset_name,
+        actual,
+        self.source,
+        self.digest,
+        if self.source == PINNED {
+          " Without release_tag and version, the release with an asset of that digest is looked up instead."
+        } else {
+          ""
+        }
+      ));
+    }
+    log!(
+      "{} matches its checksum in {}",
+      self.asset_name,
+      self.source
+    );
+    Ok(())
+  }
+}
+
+// What the asset_sha256 setting expects of `asset`. Checked instead of the release's checksums file.
+pub(crate) fn pinned(asset: &GithubReleaseAsset, digest: &str) -> Expected {
+  Expected {
+    asset_name: asset.name.clone(),
+    digest: digest.to_ascii_lowercase(),
+    source: PINNED.to_string(),
+  }
+}
+
+// The SHA-256 GitHub's release metadata lists for `asset`, or else the one the release's checksums
+// file, e.g. mpls_0.16.0_checksums.txt, lists. None when the release has neither, e.g. from a fork.
+pub(crate) fn expected(
+  release: &GithubRelease,
+  asset: &GithubReleaseAsset,
+  user_agent: Option<&str>,
+) -> zed::Result<Option<Expected>> {
+  // Zed's release lookups leave the asset digests out, so this asks the REST API itself.
+  let path = format!("repos/{}/releases/tags/{}", MPLS_REPO, release.version);
+  match release::github_get(&path, user_agent) {
+    Ok(release_json) => {
+      if let Some(digest) = api_digest(&release_json, &asset.name) {
+        return Ok(Some(Expected {
+          asset_name: asset.name.clone(),
+          digest,
+          source: API_DIGEST.to_string(),
+        }));
+      }
+    }
+    Err(err) => log!(
+      "Can't look up GitHub's digest of {}, trying the checksums file: {}",
+      asset.name,
+      err
+    ),
+  }
+
+  let Some(checksums_asset) = release
+    .assets
+    .iter()
+    .find(|asset| asset.name.to_ascii_lowercase().ends_with("checksums.txt"))
+  else {
+    log!(
+      "MPLS release {} has no checksums file, not verifying {}",
+      release.version,
+      asset.name
+    );
+    return Ok(None);
+  };
+
+  zed::download_file(
+    &checksums_asset.download_url,
+    VERIFY_PATH,
+    DownloadedFileType::Uncompressed,
+  )?;
+  let checksums = fs::read_to_string(VERIFY_PATH)
+    .map_err(|err| format!("Can't read {}: {}", checksums_asset.name, err));
+  let _ = fs::remove_file(VERIFY_PATH);
+  let digest = expected_digest(&checksums?, &asset.name).ok_or(format!(
+    "{} has no checksum for {}, so it can't be verified.",
+    checksums_asset.name, asset.name
+  ))?;
+  Ok(Some(Expected {
+    asset_name: asset.name.clone(),
+    digest,
+    source: checksums_asset.name.clone(),
+  }))
+}
+
+// Downloads `url` to `path` as it is, and returns its SHA-256. Every chunk is hashed as it arrives,
+// so the file is never read back.
+pub(crate) fn download(url: &str, path: &str, user_agent: Option<&str>) -> zed::Result<String> {
+  let stream = release::fetch_stream(url, user_agent)?;
+  let file = File::create(path).map_err(|err| format!("Can't create {}: {}", path, err))?;
+  write_hashed(|| stream.next_chunk(), file)
+}
+
+fn write_hashed(
+  mut next_chunk: impl FnMut() -> Result<Option<Vec<u8>>, String>,
+  mut out: impl Write,
+) -> Result<String, String> {
+  let mut hasher = Sha256::new();
+  while let Some(chunk) = next_chunk()? {
+    hasher.update(&chunk);
+    out.write_all(&chunk).map_err(|err| err.to_string())?;
+  }
+  out.flush().map_err(|err| err.to_string())?;
+  Ok(hex(&hasher.finalize()))
+}
+
+// The `digest` of the asset named `asset_name` in a release as the REST API returns it, e.g.
+// "sha256:ba78...". GitHub only has one for assets uploaded since it started computing them.
+fn api_digest(release_json: &serde_json::Value, asset_name: &str) -> Option<String> {
+  release_json["assets"]
+    .as_array()?
+    .iter()
+    .find(|asset| asset["name"] == asset_name)?["digest"]
+    .as_str()?
+    .strip_prefix("sha256:")
+    .filter(|digest| digest.len() == 64 && digest.bytes().all(|byte| byte.is_ascii_hexdigit()))
+    .map(str::to_ascii_lowercase)
+}
+
+// Lines as sha256sum writes them: `<digest>  <name>`, with a `*` before the name in binary mode.
+fn expected_digest(checksums: &str, asset_name: &str) -> Option<String> {
+  checksums.lines().find_map(|line| {
+    let (digest, name) = line.trim().split_once(char::is_whitespace)?;
+    (name.trim_start().trim_start_matches('*') == asset_name).then(|| digest.to_ascii_lowercase())
+  })
+}
+
+fn hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // SHA-256 of "abc", from FIPS 180-2.
+  const ABC: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+  fn asset() -> GithubReleaseAsset {
+    GithubReleaseAsset {
+      name: "mpls_0.16.0_linux_amd64.tar.gz".to_string(),
+      download_url: "https://example.com/mpls_0.16.0_linux_amd64.tar.gz".to_string(),
+    }
+  }
+
+  #[test]
+  fn checks_against_a_pinned_digest() {
+    assert!(
+      pinned(&asset(), &ABC.to_ascii_uppercase())
+        .check_digest(ABC)
+        .is_ok()
+    );
+
+    let err = pinned(&asset(), &"0".repeat(64))
+      .check_digest(ABC)
+      .unwrap_err();
+    assert!(is_mismatch(&err), "{}", err);
+    assert!(err.contains(ABC), "{}", err);
+    assert!(err.contains("release_tag"), "{}", err);
+  }
+
+  #[test]
+  fn reads_the_digest_github_lists() {
+    let release = serde_json::json!({
+      "tag_name": "v0.16.0",
+      "assets": [
+        { "name": "mpls_0.16.0_darwin_arm64.tar.gz", "digest": null },
+        { "name": "mpls_0.16.0_linux_amd64.tar.gz", "digest": format!("sha256:{}", ABC.to_ascii_uppercase()) },
+        { "name": "mpls_0.16.0_windows_amd64.zip", "digest": "md5:900150983cd24fb0d6963f7d28e17f72" },
+      ],
+    });
+    assert_eq!(
+      api_digest(&release, "mpls_0.16.0_linux_amd64.tar.gz").as_deref(),
+      Some(ABC)
+    );
+    assert_eq!(
+      api_digest(&release, "mpls_0.16.0_darwin_arm64.tar.gz"),
+      None
+    );
+    assert_eq!(api_digest(&release, "mpls_0.16.0_windows_amd64.zip"), None);
+    assert_eq!(api_digest(&release, "mpls_0.16.0_linux_arm64.tar.gz"), None);
+    assert_eq!(
+      api_digest(&serde_json::json!({}), "mpls_0.16.0_linux_amd64.tar.gz"),
+      None
+    );
+  }
+
+  #[test]
+  fn hashes_chunks_as_they_are_written() {
+    let mut chunks = vec![b"a".to_vec(), Vec::new(), b"bc".to_vec()].into_iter();
+    let mut written = Vec::new();
+    let digest = write_hashed(|| Ok(chunks.next()), &mut written).unwrap();
+    assert_eq!(digest, ABC);
+    assert_eq!(written, b"abc");
+
+    let mut failing =
+      vec![Ok(Some(b"a".to_vec())), Err("connection reset".to_string())].into_iter();
+    assert_eq!(
+      write_hashed(|| failing.next().unwrap(), Vec::new()),
+      Err("connection reset".to_string())
+    );
+  }
+}
@@ -0,0 +1,653 @@
+use crate::{
+  AssetNaming, LANGUAGE_SERVER_NAME, LookupFailure, MPLS_REPO, Metadata, Mpls, MplsSettings,
+  assets, binary, installs,
+  settings::{INSTALLED_LATEST, PlatformFallback},
+  version::Version,
+};
+use std::{fmt::Write, fs};
+use zed_extension_api as zed;
+
+// /mpls-doctor
+pub(crate) fn doctor(
+  mpls: &Mpls,
+  worktree: Option<&zed::Worktree>,
+) -> zed::Result<zed::SlashCommandOutput> {
+  let settings = settings(worktree)?;
+  // An unsupported platform still gets the parts of the report that don't depend on release assets.
+  let naming = AssetNaming::for_platform(&settings);
+  let resolved = worktree.and_then(|worktree| {
+    mpls
+      .language_server_paths()
+      .get(&worktree.id())
+      .map(|cached| cached.resolved.clone())
+  });
+  let install_dir = std::env::current_dir()
+    .map(|dir| dir.display().to_string())
+    .unwrap_or_else(|err| format!("unknown ({})", err));
+
+  // Writing into a String never fails, so the results of writeln! are ignored.
+  let mut text = String::new();
+  match &naming {
+    Ok(naming) => {
+      let _ = writeln!(text, "Platform: {}/{}", naming.os, naming.arch);
+    }
+    Err(_) => {
+      let (os, arch) = zed::current_platform();
+      let _ = writeln!(text, "Platform: {:?} on {:?} (unsupported)", arch, os);
+    }
+  }
+  let _ = writeln!(
+    text,
+    "Supported platforms: {}",
+    crate::supported_platforms().join(", ")
+  );
+  match &resolved {
+    Some(resolved) => {
+      let _ = writeln!(text, "Binary: {} ({})", resolved.path, resolved.source);
+    }
+    None => {
+      let _ = writeln!(text, "Binary: not resolved yet");
+    }
+  }
+  let _ = writeln!(text, "Arguments: {}", settings.arguments().join(" "));
+  let _ = writeln!(text, "Install directory: {}", install_dir);
+  let _ = writeln!(
+    text,
+    "Activated with /mpls-use: {}",
+    Metadata::load().active_version.as_deref().unwrap_or("none")
+  );
+  if let Some(active_profile) = &settings.active_profile {
+    let _ = writeln!(text, "Profile: {}", active_profile);
+  }
+  let _ = writeln!(text, "Channel: {}", settings.channel.as_str());
+  let _ = writeln!(
+    text,
+    "Pinned version: {}",
+    if settings.wants_installed_latest() {
+      INSTALLED_LATEST
+    } else {
+      settings.pinned_version().unwrap_or("none")
+    }
+  );
+  let _ = writeln!(text, "Offline only: {}", settings.offline_only);
+  let naming = match naming {
+    Ok(naming) => naming,
+    Err(err) => {
+      let _ = writeln!(text, "Platform fallback: {:?}", settings.platform_fallback);
+      let _ = writeln!(text, "{}", err);
+      return Ok(output("MPLS doctor", text));
+    }
+  };
+  let _ = writeln!(
+    text,
+    "Asset name: {}",
+    naming.archived("<version>", naming.ext())
+  );
+
+  let metadata = Metadata::load();
+  let installed_version = installs::installations(&naming)?
+    .into_iter()
+    .rev()
+    .find(|installation| settings.channel.accepts(&installation.version))
+    .map(|installation| installation.version);
+  let latest_version = metadata
+    .latest_versions
+    .get(settings.channel.as_str())
+    .and_then(|version| Version::parse(version).ok());
+  let _ = writeln!(
+    text,
+    "Installed: {}, latest: {}",
+    installed_version
+      .as_ref()
+      .map_or("none".to_string(), Version::to_string),
+    match (&installed_version, &latest_version) {
+      (_, None) => "unknown, not looked up yet".to_string(),
+      (Some(installed), Some(latest)) if installed >= latest => {
+        format!("{} (up to date, as of the last lookup)", latest)
+      }
+      (_, Some(latest)) => format!(
+        "{} (newer available, as of the last lookup; run /mpls-update)",
+        latest
+      ),
+    }
+  );
+
+  let failed_installs = metadata.failed_installs;
+  let usage = installs::disk_usage(&naming)?;
+  let _ = writeln!(text, "Installed versions:");
+  if usage.per_installation.is_empty() {
+    let _ = writeln!(text, "  (none)");
+  }
+  for (installation, size) in &usage.per_installation {
+    let is_active = resolved.as_ref().is_some_and(|resolved| {
+      resolved
+        .path
+        .starts_with(&format!("{}/", installation.dir_name))
+    });
+    let _ = writeln!(
+      text,
+      "  {}  {}{}{}",
+      installation.version_string(),
+      installs::format_bytes(*size),
+      if is_active { "  (active)" } else { "" },
+      if failed_installs.contains(&installation.dir_name) {
+        "  (failed its check, not used)"
+      } else {
+        ""
+      }
+    );
+  }
+  let _ = writeln!(text, "Total: {}", installs::format_bytes(usage.total()));
+  for skipped in &usage.skipped {
+    let _ = writeln!(text, "Skipped unreadable entry {}", skipped);
+  }
+
+  let _ = writeln!(text, "Offline scan of {}:", install_dir);
+  let _ = writeln!(
+    text,
+    "  expects: {}",
+    naming.executable_path(&naming.unarchived("<version>"))
+  );
+  let _ = writeln!(text, "  pattern: {}", naming.unarchived_regex().as_str());
+  for (name, verdict) in installs::scan_report(&naming)? {
+    let _ = writeln!(text, "  {}: {}", name, verdict);
+  }
+
+  Ok(output("MPLS doctor", text))
+}
+
+// Passing this to /mpls-use clears the active version.
+const AUTOMATIC_VERSION: &str = "latest";
+
+// /mpls-use [version]
+pub(crate) fn use_version(
+  mpls: &Mpls,
+  args: &[String],
+  worktree: Option<&zed::Worktree>,
+) -> zed::Result<zed::SlashCommandOutput> {
+  let naming = AssetNaming::for_platform(&settings(worktree)?)?;
+  let installations = installs::installations(&naming)?;
+  let mut metadata = Metadata::load();
+
+  let Some(requested) = args.first() else {
+    let mut text = String::from("Installed versions:\n");
+    for installation in installations.iter().rev() {
+      let version = installation.version_string();
+      let is_active = metadata.active_version.as_ref() == Some(&version);
+      let _ = writeln!(
+        text,
+        "  {}{}",
+        version,
+        if is_active { "  (active)" } else { "" }
+      );
+    }
+    if metadata.active_version.is_none() {
+      let _ = writeln!(
+        text,
+        "No version is pinned; MPLS is resolved automatically."
+      );
+    }
+    return Ok(output("MPLS versions", text));
+  };
+
+  let requested = requested.trim_start_matches('v');
+  if requested == AUTOMATIC_VERSION {
+    metadata.active_version = None;
+    metadata.save()?;
+    mpls.language_server_paths().clear();
+    return Ok(output(
+      "MPLS versions",
+      "MPLS will be resolved automatically. Restart the language server to apply it.".to_string(),
+    ));
+  }
+
+  if !installations
+    .iter()
+    .any(|installation| installation.version_string() == requested)
+  {
+    let available: Vec<String> = installations
+      .iter()
+      .rev()
+      .map(installs::Installation::version_string)
+      .collect();
+    return Err(format!(
+      "MPLS {} is not installed. Available: {}",
+      requested,
+      if available.is_empty() {
+        "none".to_string()
+      } else {
+        available.join(", ")
+      }
+    ));
+  }
+
+  metadata.active_version = Some(requested.to_string());
+  metadata.save()?;
+  mpls.language_server_paths().clear();
+  Ok(output(
+    "MPLS versions",
+    format!(
+      "MPLS {} is now active. Restart the language server to apply it.",
+      requested
+    ),
+  ))
+}
+
+// Completion doesn't get a worktree, so this can only see installations that use the default naming.
+pub(crate) fn complete_use_version() -> zed::Result<Vec<zed::SlashCommandArgumentCompletion>> {
+  let naming = AssetNaming::for_platform(&MplsSettings::default())?;
+  let mut versions: Vec<String> = installs::installations(&naming)?
+    .iter()
+    .rev()
+    .map(installs::Installation::version_string)
+    .collect();
+  versions.push(AUTOMATIC_VERSION.to_string());
+  Ok(
+    versions
+      .into_iter()
+      .map(|version| zed::SlashCommandArgumentCompletion {
+        label: version.clone(),
+        new_text: version,
+        run_command: true,
+      })
+      .collect(),
+  )
+}
+
+// /mpls-update
+pub(crate) fn update(
+  mpls: &Mpls,
+  worktree: Option<&zed::Worktree>,
+) -> zed::Result<zed::SlashCommandOutput> {
+  let settings = settings(worktree)?;
+  if settings.offline_only {
+    return Err("offline_only is set, so /mpls-update won't contact GitHub.".to_string());
+  }
+  if settings.wants_installed_latest() {
+    return Err(
+      "version is set to \"installed-latest\", so /mpls-update won't download anything."
+        .to_string(),
+    );
+  }
+  if let Some(version) = settings.pinned_version() {
+    return Ok(output(
+      "MPLS update",
+      format!(
+        "MPLS is pinned to {} by the `{}` setting. Change the pin to update.",
+        version,
+        settings.pin_setting_name()
+      ),
+    ));
+  }
+
+  let naming = AssetNaming::for_platform(&settings)?;
+  let installed_version = installs::installations(&naming)?
+    .iter()
+    .rev()
+    .find(|installation| settings.channel.accepts(&installation.version))
+    .map(installs::Installation::version_string);
+  let release = zed::latest_github_release(MPLS_REPO, settings.release_options())?;
+  crate::record_latest_version(&settings, &release);
+  let latest_version = crate::release_version(&release, &settings)?;
+  if installed_version.as_deref() == Some(latest_version.as_str()) {
+    return Ok(output(
+      "MPLS update",
+      format!("MPLS {} is already the latest version.", latest_version),
+    ));
+  }
+
+  let resolved = mpls.when_online(&release, None, worktree, &settings)?;
+  // Every worktree resolves again on its next launch, picking up the new version.
+  mpls.language_server_paths().clear();
+  let mut text = if matches!(resolved.source, crate::Source::RolledBack) {
+    format!(
+      "MPLS {} failed its check after downloading, so MPLS rolled back to {}. See the Zed log for why.",
+      latest_version, resolved.path
+    )
+  } else {
+    format!(
+      "Updated MPLS from {} to {}. Restart the language server to use it.",
+      installed_version.as_deref().unwrap_or("nothing"),
+      latest_version
+    )
+  };
+  if let Some(version) = Metadata::load().active_version {
+    let _ = write!(
+      text,
+      "\nMPLS {} is still active from /mpls-use and takes precedence. Run /mpls-use latest to switch to the new version.",
+      version
+    );
+  }
+  Ok(output("MPLS update", text))
+}
+
+// /mpls-plan
+// Walks the same steps as resolve_language_server and reports what it would do,
+// without downloading, installing or writing anything.
+pub(crate) fn plan(
+  mpls: &Mpls,
+  worktree: Option<&zed::Worktree>,
+) -> zed::Result<zed::SlashCommandOutput> {
+  let settings = settings(worktree)?;
+  let decision = plan_decision(mpls, worktree, &settings)?;
+  Ok(output("MPLS plan", format!("{}\n", decision)))
+}
+
+fn plan_decision(
+  mpls: &Mpls,
+  worktree: Option<&zed::Worktree>,
+  settings: &MplsSettings,
+) -> zed::Result<String> {
+  if let Some(worktree) = worktree
+    && let Some(cached) = mpls.language_server_paths().get(&worktree.id())
+    && cached.resolution_key == settings.resolution_key()
+  {
+    return Ok(format!(
+      "Reuse {} ({}), resolved earlier in this session.",
+      cached.resolved.path, cached.resolved.source
+    ));
+  }
+
+  if let Some(path) = &settings.binary_path {
+    return Ok(format!("Use {} from lsp.mpls.binary.path.", path));
+  }
+
+  if let Some(version) = Metadata::load().active_version {
+    let naming = match AssetNaming::for_platform(settings) {
+      Ok(naming) => naming,
+      Err(err) => return Ok(format!("Fail: {}", err)),
+    };
+    let installed = installs::installations(&naming)?
+      .iter()
+      .any(|installation| installation.version_string() == version);
+    return Ok(if installed {
+      format!("Use MPLS {}, activated with /mpls-use.", version)
+    } else {
+      format!(
+        "Fail: MPLS {} was activated with /mpls-use but it's no longer installed.",
+        version
+      )
+    });
+  }
+
+  if settings.use_path_binary
+    && let Some(path) = worktree.and_then(|worktree| {
+      worktree
+        .which(&settings.binary_name)
+        .and_then(|path| crate::absolute_path_binary(worktree, path))
+    })
+    && crate::path_binary_satisfies_pin(&path, settings)
+  {
+    return Ok(format!("Use {} from PATH.", path));
+  }
+
+  let naming = match AssetNaming::for_platform(settings) {
+    Ok(naming) => naming,
+    Err(err) => {
+      return Ok(match settings.platform_fallback {
+        PlatformFallback::Error => format!("Fail: {}", err),
+        PlatformFallback::Path => match worktree.and_then(|worktree| {
+          worktree
+            .which(&settings.binary_name)
+            .and_then(|path| crate::absolute_path_binary(worktree, path))
+        }) {
+          Some(path) => format!(
+            "Use {} from PATH, because platform_fallback is \"path\".",
+            path
+          ),
+          None => format!(
+            "Fail: {} platform_fallback is \"path\", but {} isn't on it.",
+            err, settings.binary_name
+          ),
+        },
+      });
+    }
+  };
+
+  if settings.offline_only {
+    return plan_offline(settings, &naming, "offline_only is set");
+  }
+  if settings.wants_installed_latest() {
+    return plan_offline(settings, &naming, "version is \"installed-latest\"");
+  }
+  // A launch probes by writing a file; the plan only looks at the permissions.
+  if fs::metadata(".").is_ok_and(|metadata| metadata.permissions().readonly()) {
+    return plan_offline(settings, &naming, "the install directory is read-only");
+  }
+
+  if settings.network_probe
+    && let Err(err) = crate::release::probe_github(settings.user_agent.as_deref())
+  {
+    return plan_offline(settings, &naming, &err);
+  }
+
+  let release = match crate::fetch_release(settings) {
+    Ok(release) => release,
+    Err(err) => {
+      return match (LookupFailure::classify(&err), settings.pinned_tag()) {
+        (LookupFailure::NotFound, Some(tag)) => Ok(format!(
+          "Fail: mpls release {} not found in {} releases.",
+          tag, MPLS_REPO
+        )),
+        _ => plan_offline(
+          settings,
+          &naming,
+          &format!("the release lookup failed: {}", err),
+        ),
+      };
+    }
+  };
+
+  let version = crate::release_version(&release, settings)?;
+  let unarchived_asset_name = naming.unarchived(&version);
+  let executable_path = naming.executable_path(&unarchived_asset_name);
+  let metadata = Metadata::load();
+  let is_stale = crate::is_stale_install(&metadata, &unarchived_asset_name, settings);
+  let is_installed = matches!(fs::exists(&executable_path), Ok(true)) && !is_stale;
+  // Telling whether the tag was re-published means downloading its checksums file, which a plan doesn't.
+  let republish_note = if is_installed
+    && settings.verify_checksums
+    && settings.asset_sha256.is_none()
+    && metadata.asset_digests.contains_key(&unarchived_asset_name)
+  {
+    " A launch first checks the release's checksums file, and downloads again if the release was re-published with different assets."
+  } else {
+    ""
+  };
+  if is_installed && metadata.failed_installs.contains(&unarchived_asset_name) {
+    return Ok(format!(
+      "Roll back from MPLS {}, which failed its check when it was downloaded.",
+      release.version
+    ));
+  }
+  if is_installed {
+    return Ok(format!(
+      "Reuse {}, which is already installed for MPLS {}.{}",
+      executable_path, release.version, republish_note
+    ));
+  }
+
+  if assets::not_uploaded_yet(&release, settings).is_some() {
+    return plan_offline(
+      settings,
+      &naming,
+      &format!(
+        "MPLS release {} has no binaries uploaded yet",
+        release.version
+      ),
+    );
+  }
+  let asset = match assets::find_preferred_asset(&release.assets, &naming, &version, settings) {
+    Ok((asset, _)) => asset,
+    Err(tried) => {
+      return plan_offline(
+        settings,
+        &naming,
+        &format!(
+          "MPLS release {} has no asset named {}",
+          release.version,
+          tried.join(" or ")
+        ),
+      );
+    }
+  };
+  Ok(format!(
+    "Download {} into {}{}.",
+    asset.download_url,
+    unarchived_asset_name,
+    if is_stale {
+      ", replacing an install made with different asset settings"
+    } else {
+      ""
+    }
+  ))
+}
+
+fn plan_offline(
+  settings: &MplsSettings,
+  naming: &AssetNaming,
+  reason: &str,
+) -> zed::Result<String> {
+  Ok(match crate::offline_executable(settings, naming)? {
+    Some(executable_path) => format!("Use installed {}, because {}.", executable_path, reason),
+    None => format!(
+      "Fail: {}, and no installed version matches the settings.",
+      reason
+    ),
+  })
+}
+
+// /mpls-validate
+pub(crate) fn validate(worktree: Option<&zed::Worktree>) -> zed::Result<zed::SlashCommandOutput> {
+  let worktree = worktree.ok_or("/mpls-validate needs an open project to read its settings.")?;
+  let report = MplsSettings::check(LANGUAGE_SERVER_NAME, worktree)?;
+
+  let mut text = String::new();
+  if report.errors.is_empty() {
+    let _ = writeln!(text, "The MPLS settings are valid.");
+  } else {
+    let _ = writeln!(text, "The MPLS settings are invalid:");
+  }
+  for error in &report.errors {
+    let _ = writeln!(text, "  error: {}", error);
+  }
+  for warning in &report.warnings {
+    let _ = writeln!(text, "  warning: {}", warning);
+  }
+  Ok(output("MPLS settings", text))
+}
+
+// /mpls-export
+// Extensions can't write into the worktree, so this prints the file for the user to save.
+pub(crate) fn export(worktree: Option<&zed::Worktree>) -> zed::Result<zed::SlashCommandOutput> {
+  let settings = settings(worktree)?;
+  let mut server = zed::serde_json::json!({ "settings": settings.non_default_json()? });
+  if let Some(binary) = settings.binary_json() {
+    server["binary"] = binary;
+  }
+  let json = zed::serde_json::json!({ "lsp": { LANGUAGE_SERVER_NAME: server } });
+  let json = zed::serde_json::to_string_pretty(&json).map_err(|err| err.to_string())?;
+  Ok(output(
+    "MPLS settings export",
+    format!(
+      "Save this as .zed/settings.json in the project (or merge it into an existing one) to share these settings. Only settings that differ from the defaults are included.\n\n{}\n",
+      json
+    ),
+  ))
+}
+
+// /mpls-repair
+// Checks every installed version the way a fresh download is checked, and removes the ones that
+// fail, so that the next start resolves to the newest good one.
+pub(crate) fn repair(
+  mpls: &Mpls,
+  worktree: Option<&zed::Worktree>,
+) -> zed::Result<zed::SlashCommandOutput> {
+  let naming = AssetNaming::for_platform(&settings(worktree)?)?;
+  let running: Vec<String> = mpls
+    .language_server_paths()
+    .values()
+    .map(|cached| cached.resolved.path.clone())
+    .collect();
+  let mut metadata = Metadata::load();
+
+  let mut text = String::new();
+  let (mut kept, mut removed) = (0, 0);
+  for installation in installs::installations(&naming)?.iter().rev() {
+    let version = installation.version_string();
+    let dir_name = &installation.dir_name;
+    let executable_path = installation.executable_path();
+    // Setting the executable bit again is part of the repair, e.g. after a copy that lost it.
+    let problem = if fs::metadata(&executable_path).is_err() {
+      Some(format!("{} is missing", executable_path))
+    } else {
+      binary::make_executable(&executable_path)
+        .and_then(|()| binary::check_runs(&executable_path))
+        .err()
+    };
+    let Some(problem) = problem else {
+      metadata.failed_installs.retain(|failed| failed != dir_name);
+      kept += 1;
+      let _ = writeln!(text, "  {}: kept", version);
+      continue;
+    };
+    if running
+      .iter()
+      .any(|path| path.starts_with(&format!("{}/", dir_name)))
+    {
+      kept += 1;
+      let _ = writeln!(
+        text,
+        "  {}: broken ({}), but kept because a language server is running it",
+        version, problem
+      );
+      continue;
+    }
+    match fs::remove_dir_all(dir_name) {
+      Ok(()) => {
+        removed += 1;
+        metadata.forget_install(dir_name);
+        if metadata.active_version.as_ref() == Some(&version) {
+          metadata.active_version = None;
+        }
+        if metadata.last_good_version.as_ref() == Some(&version) {
+          metadata.last_good_version = None;
+        }
+        let _ = writeln!(text, "  {}: removed ({})", version, problem);
+      }
+      Err(err) => {
+        let _ = writeln!(
+          text,
+          "  {}: broken ({}), but can't be removed: {}",
+          version, problem, err
+        );
+      }
+    }
+  }
+  metadata.save()?;
+  mpls.language_server_paths().clear();
+
+  let summary = format!(
+    "Kept {} and removed {} installed versions of MPLS. Restart the language server to use the newest good one.\n",
+    kept, removed
+  );
+  Ok(output("MPLS repair", summary + &text))
+}
+
+// Slash commands may run without a worktree, in which case only the defaults are known.
+fn settings(worktree: Option<&zed::Worktree>) -> zed::Result<MplsSettings> {
+  match worktree {
+    Some(worktree) => MplsSettings::for_worktree(LANGUAGE_SERVER_NAME, worktree),
+    None => Ok(MplsSettings::default()),
+  }
+}
+
+fn output(label: &str, text: String) -> zed::SlashCommandOutput {
+  zed::SlashCommandOutput {
+    sections: vec![zed::SlashCommandOutputSection {
+      range: (0..text.len()).into(),
+      label: label.to_string(),
+    }],
+    text,
+  }
+}
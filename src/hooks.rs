@@ -0,0 +1,53 @@
+use crate::binary;
+use zed_extension_api as zed;
+
+// Runs the user's post_install_command with the absolute path of the freshly installed binary appended.
+// A non-zero exit fails the install, so e.g. antivirus or notarization checks can veto a download.
+pub(crate) fn run_post_install(
+  post_install_command: &[String],
+  worktree: Option<&zed::Worktree>,
+  executable_path: &str,
+) -> zed::Result<()> {
+  // Settings validation makes sure the command isn't empty.
+  let (program, args) = post_install_command
+    .split_first()
+    .ok_or("post_install_command is empty")?;
+
+  // We can only look the command up when we know which PATH to search.
+  let is_bare_name = !program.contains('/') && !program.contains('\\');
+  let program = match worktree {
+    Some(worktree) if is_bare_name => worktree.which(program).ok_or(format!(
+      "post_install_command {} was not found on your PATH.",
+      program
+    ))?,
+    _ => program.clone(),
+  };
+
+  let executable_path = binary::host_path(executable_path)?;
+  let output = zed::process::Command::new(&program)
+    .args(args)
+    .arg(&executable_path)
+    .output()?;
+
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  log!(
+    "post_install_command {} exited with {:?}\nstdout: {}\nstderr: {}",
+    program,
+    output.status,
+    stdout.trim_end(),
+    stderr.trim_end()
+  );
+
+  if output.status == Some(0) {
+    Ok(())
+  } else {
+    Err(format!(
+      "post_install_command {} rejected {} (exit status {:?}): {}",
+      program,
+      executable_path,
+      output.status,
+      stderr.trim_end()
+    ))
+  }
+}
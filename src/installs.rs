@@ -0,0 +1,392 @@
+use crate::{metadata, naming::AssetNaming, version::Version};
+use regex::Regex;
+use std::{
+  collections::HashMap,
+  env::current_dir,
+  fs,
+  io::ErrorKind,
+  path::{Path, PathBuf},
+};
+use zed_extension_api as zed;
+
+// A version directory left behind by a previous download, e.g. mpls_0.16.0_linux_amd64
+// or whatever asset_name_template turns that into.
+pub(crate) struct Installation {
+  pub(crate) version: Version,
+  pub(crate) dir_name: String,
+  executable_path: String,
+}
+
+impl Installation {
+  pub(crate) fn version_string(&self) -> String {
+    self.version.to_string()
+  }
+
+  pub(crate) fn executable_path(&self) -> String {
+    self.executable_path.clone()
+  }
+}
+
+// Returns every installation for the given platform in the extension's work directory, oldest first.
+pub(crate) fn installations(naming: &AssetNaming) -> zed::Result<Vec<Installation>> {
+  let mut dir_names = Vec::new();
+  for dir in current_dir()
+    .and_then(fs::read_dir)
+    .map_err(|err| err.to_string())?
+  {
+    let dir = dir.map_err(|err| err.to_string())?;
+    if !dir.file_type().map_err(|err| err.to_string())?.is_dir() {
+      continue;
+    }
+
+    let dirname = dir.file_name();
+    let dirname = dirname
+      .to_str()
+      .ok_or("dirname contains invalid UTF-8 string")?;
+    dir_names.push(dirname.to_string());
+  }
+  Ok(from_dir_names(&dir_names, naming))
+}
+
+// The installations among these directory names, oldest first.
+pub(crate) fn from_dir_names(dir_names: &[String], naming: &AssetNaming) -> Vec<Installation> {
+  let scan_regexes = naming.scan_regexes();
+  let mut installations: Vec<Installation> = dir_names
+    .iter()
+    .filter_map(|dir_name| {
+      let version = parse_version_dir(dir_name, &scan_regexes)?;
+      Some(Installation {
+        version,
+        dir_name: dir_name.clone(),
+        executable_path: naming.executable_path(dir_name),
+      })
+    })
+    .collect();
+  // Semver ordering puts pre-releases before their release, and orders nightlies by their suffix.
+  installations.sort_by(|a, b| a.version.cmp(&b.version));
+  installations
+}
+
+// The version in a directory name, if it's a version directory for this platform.
+// scan_regexes comes from AssetNaming::scan_regexes(), compiled once per scan.
+fn parse_version_dir(name: &str, scan_regexes: &[Regex]) -> Option<Version> {
+  scan_regexes
+    .iter()
+    .find_map(|regex| regex.captures(name))
+    .and_then(|captures| Version::parse(&captures[1]).ok())
+}
+
+// Every entry of the work directory with why the offline scan does or doesn't count it,
+// for /mpls-doctor. Mirrors installations(), which it has to be kept in step with.
+pub(crate) fn scan_report(naming: &AssetNaming) -> zed::Result<Vec<(String, String)>> {
+  let ours = naming.scan_regexes();
+  let any = naming.any_platform_regex();
+  let mut report = Vec::new();
+  for entry in current_dir()
+    .and_then(fs::read_dir)
+    .map_err(|err| err.to_string())?
+  {
+    let entry = entry.map_err(|err| err.to_string())?;
+    let name = entry.file_name().to_string_lossy().into_owned();
+    let verdict = if !entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+      "skipped, not a directory".to_string()
+    } else if let Some(captures) = ours.iter().find_map(|regex| regex.captures(&name)) {
+      let executable_path = naming.executable_path(&name);
+      match Version::parse(&captures[1]) {
+        Err(err) => format!("skipped, {} isn't a valid version: {}", &captures[1], err),
+        Ok(version) if !Path::new(&executable_path).exists() => format!(
+          "matches MPLS {}, but {} is missing",
+          version, executable_path
+        ),
+        Ok(version) => format!("ok, MPLS {}", version),
+      }
+    } else if any.is_match(&name) {
+      "skipped, installed for another platform".to_string()
+    } else {
+      "skipped, doesn't match the pattern".to_string()
+    };
+    report.push((name, verdict));
+  }
+  report.sort();
+  Ok(report)
+}
+
+// Names of version directories that were installed for a different os/arch than ours.
+pub(crate) fn other_platform_dir_names(naming: &AssetNaming) -> Vec<String> {
+  let ours = naming.scan_regexes();
+  let any = naming.any_platform_regex();
+  let Ok(entries) = current_dir().and_then(fs::read_dir) else {
+    return Vec::new();
+  };
+  let mut dir_names: Vec<String> = entries
+    .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+    .filter(|dir_name| any.is_match(dir_name) && !ours.iter().any(|regex| regex.is_match(dir_name)))
+    .collect();
+  dir_names.sort();
+  dir_names
+}
+
+// Replaces the binary of a new installation with a hard link to an identical binary from another one.
+// The installations keep their own directories, so the offline scan and /mpls-use don't notice,
+// and removing either directory leaves the other intact. checksums caches fnv1a() per directory.
+pub(crate) fn dedupe(
+  naming: &AssetNaming,
+  dir_name: &str,
+  executable_path: &str,
+  checksums: &mut HashMap<String, String>,
+) {
+  let Ok(contents) = fs::read(executable_path) else {
+    return;
+  };
+  let checksum = metadata::fnv1a(&contents);
+  checksums.insert(dir_name.to_string(), checksum.clone());
+
+  for installation in installations(naming).unwrap_or_default() {
+    if installation.dir_name == dir_name {
+      continue;
+    }
+    let other_checksum = match checksums.get(&installation.dir_name) {
+      Some(other_checksum) => other_checksum.clone(),
+      None => match fs::read(&installation.executable_path) {
+        Ok(other) => {
+          let other_checksum = metadata::fnv1a(&other);
+          checksums.insert(installation.dir_name.clone(), other_checksum.clone());
+          other_checksum
+        }
+        Err(_) => continue,
+      },
+    };
+    // The checksum only narrows it down; the bytes decide.
+    if other_checksum != checksum
+      || fs::read(&installation.executable_path).ok().as_ref() != Some(&contents)
+    {
+      continue;
+    }
+
+    let link_path = format!("{}.dedupe", executable_path);
+    let _ = fs::remove_file(&link_path);
+    match fs::hard_link(&installation.executable_path, &link_path)
+      .and_then(|()| fs::rename(&link_path, executable_path))
+    {
+      Ok(()) => log!(
+        "{} is identical to {}, sharing it",
+        executable_path,
+        installation.executable_path
+      ),
+      Err(err) => {
+        let _ = fs::remove_file(&link_path);
+        log!("Can't dedupe {}: {}", executable_path, err);
+      }
+    }
+    return;
+  }
+}
+
+// Archives that don't extract the binary to `dir_name/<executable_subpath>` (e.g. because of a `bin/`
+// or versioned root folder inside) get it moved there, where the fast path and the offline scan look.
+pub(crate) fn normalize_layout(dir_name: &str, executable_subpath: &str) -> zed::Result<()> {
+  let expected = Path::new(dir_name).join(executable_subpath);
+  if expected.exists() {
+    return Ok(());
+  }
+  let executable_name = expected.file_name().unwrap_or_default();
+
+  let mut files = Vec::new();
+  collect_files(Path::new(dir_name), &mut files);
+  let Some(found) = files
+    .iter()
+    .find(|file| file.file_name() == Some(executable_name))
+  else {
+    let layout: Vec<String> = files
+      .iter()
+      .map(|file| file.display().to_string())
+      .collect();
+    return Err(format!(
+      "The archive doesn't contain {}. It extracted to: {}",
+      executable_subpath,
+      if layout.is_empty() {
+        "nothing".to_string()
+      } else {
+        layout.join(", ")
+      }
+    ));
+  };
+
+  log!("Moving {} to {}", found.display(), expected.display());
+  if let Some(parent) = expected.parent() {
+    let _ = fs::create_dir_all(parent);
+  }
+  fs::rename(found, &expected).map_err(|err| {
+    format!(
+      "Can't move {} to {}: {}",
+      found.display(),
+      expected.display(),
+      err
+    )
+  })
+}
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) {
+  let Ok(entries) = fs::read_dir(path) else {
+    return;
+  };
+  for entry in entries.flatten() {
+    let path = entry.path();
+    if path.is_dir() {
+      collect_files(&path, files);
+    } else {
+      files.push(path);
+    }
+  }
+}
+
+// Recognizes ENOSPC and its Windows counterparts in the error messages of a download or an extraction.
+pub(crate) fn is_out_of_space(err: &str) -> bool {
+  let err = err.to_ascii_lowercase();
+  [
+    "no space left",
+    "os error 28",
+    "disk full",
+    "not enough space",
+    "os error 112",
+  ]
+  .iter()
+  .any(|needle| err.contains(needle))
+}
+
+// Downloading needs a writable work directory, which locked-down setups don't give us.
+pub(crate) fn work_dir_is_read_only() -> bool {
+  const PROBE_PATH: &str = ".mpls-write-probe";
+  match fs::write(PROBE_PATH, b"") {
+    Ok(()) => {
+      let _ = fs::remove_file(PROBE_PATH);
+      false
+    }
+    Err(err) => matches!(
+      err.kind(),
+      ErrorKind::PermissionDenied | ErrorKind::ReadOnlyFilesystem
+    ),
+  }
+}
+
+pub(crate) struct DiskUsage {
+  pub(crate) per_installation: Vec<(Installation, u64)>,
+  // Paths we couldn't measure, with the reason.
+  pub(crate) skipped: Vec<String>,
+}
+
+impl DiskUsage {
+  pub(crate) fn total(&self) -> u64 {
+    self.per_installation.iter().map(|(_, size)| size).sum()
+  }
+}
+
+pub(crate) fn disk_usage(naming: &AssetNaming) -> zed::Result<DiskUsage> {
+  let mut skipped = Vec::new();
+  let per_installation = installations(naming)?
+    .into_iter()
+    .map(|installation| {
+      let size = dir_size(Path::new(&installation.dir_name), &mut skipped);
+      (installation, size)
+    })
+    .collect();
+  Ok(DiskUsage {
+    per_installation,
+    skipped,
+  })
+}
+
+// Unreadable entries are skipped and noted instead of failing the whole walk.
+fn dir_size(path: &Path, skipped: &mut Vec<String>) -> u64 {
+  let entries = match fs::read_dir(path) {
+    Ok(entries) => entries,
+    Err(err) => {
+      skipped.push(format!("{}: {}", path.display(), err));
+      return 0;
+    }
+  };
+
+  let mut size = 0;
+  for entry in entries {
+    let entry = match entry {
+      Ok(entry) => entry,
+      Err(err) => {
+        skipped.push(format!("{}: {}", path.display(), err));
+        continue;
+      }
+    };
+    match entry.metadata() {
+      Ok(metadata) if metadata.is_dir() => size += dir_size(&entry.path(), skipped),
+      Ok(metadata) => size += metadata.len(),
+      Err(err) => skipped.push(format!("{}: {}", entry.path().display(), err)),
+    }
+  }
+  size
+}
+
+pub(crate) fn format_bytes(bytes: u64) -> String {
+  const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+  let mut value = bytes as f64;
+  let mut unit = 0;
+  while value >= 1024.0 && unit < UNITS.len() - 1 {
+    value /= 1024.0;
+    unit += 1;
+  }
+  if unit == 0 {
+    format!("{} {}", bytes, UNITS[unit])
+  } else {
+    format!("{:.1} {}", value, UNITS[unit])
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::MplsSettings;
+
+  fn parse(name: &str) -> Option<Version> {
+    let naming = AssetNaming::new(&MplsSettings::default(), "linux", "amd64");
+    parse_version_dir(name, &naming.scan_regexes())
+  }
+
+  #[test]
+  fn parses_version_dirs_of_this_platform() {
+    assert_eq!(
+      parse("mpls_0.16.0_linux_amd64"),
+      Some(Version::new(0, 16, 0))
+    );
+    // The other install_layout is scanned too.
+    assert_eq!(parse("mpls-0.16.0"), Some(Version::new(0, 16, 0)));
+  }
+
+  #[test]
+  fn skips_other_platforms() {
+    assert_eq!(parse("mpls_0.16.0_darwin_arm64"), None);
+    assert_eq!(parse("mpls_0.16.0_linux_arm64"), None);
+  }
+
+  #[test]
+  fn skips_malformed_names() {
+    for name in [
+      "mpls_0.16_linux_amd64",
+      "mpls_v0.16.0_linux_amd64",
+      "mpls_0.16.0_linux_amd64.tar.gz",
+      "mpls_0.16.0_linux_amd64_old",
+      "mpls-metadata.json",
+      "",
+    ] {
+      assert_eq!(parse(name), None, "{}", name);
+    }
+  }
+
+  #[test]
+  fn parses_pre_releases() {
+    let version = parse("mpls_0.17.0-nightly.20250101.abc1234_linux_amd64").unwrap();
+    assert_eq!((version.major, version.minor, version.patch), (0, 17, 0));
+    assert_eq!(version.pre.as_str(), "nightly.20250101.abc1234");
+    assert_eq!(
+      parse("mpls-0.17.0-rc.1").map(|version| version.to_string()),
+      Some("0.17.0-rc.1".to_string())
+    );
+  }
+}
@@ -1,41 +1,365 @@
-use regex::Regex;
-use std::{env::current_dir, fs};
+// Extensions' stderr ends up in Zed's log, which is where users look when an install goes wrong.
+// Defined before the modules so they can use it too.
+macro_rules! log {
+  ($($arg:tt)*) => {
+    eprintln!("[mpls] {}", format!($($arg)*))
+  };
+}
+
+mod archive;
+mod assets;
+mod binary;
+mod checksums;
+mod commands;
+mod hooks;
+mod installs;
+mod metadata;
+mod naming;
+mod offline;
+mod release;
+mod retry;
+mod settings;
+mod version;
+
+use metadata::Metadata;
+use naming::AssetNaming;
+use offline::OfflineCause;
+use release::LookupFailure;
+use settings::{MplsSettings, PlatformFallback};
+use std::{
+  collections::HashMap,
+  fs,
+  sync::{Mutex, MutexGuard, PoisonError},
+};
 use zed_extension_api::{self as zed, GithubRelease};
 
+const MPLS_REPO: &str = "mhersson/mpls";
+// Must match [language_servers.mpls] in extension.toml.
+const LANGUAGE_SERVER_NAME: &str = "mpls";
+
+// Every platform mpls publishes builds for, with the os and arch as they appear in asset names.
+// platform() looks the host up here, so the two can't drift apart.
+const SUPPORTED_PLATFORMS: [(zed::Os, zed::Architecture, &str, &str); 6] = [
+  (zed::Os::Linux, zed::Architecture::X8664, "linux", "amd64"),
+  (zed::Os::Linux, zed::Architecture::Aarch64, "linux", "arm64"),
+  (zed::Os::Mac, zed::Architecture::X8664, "darwin", "amd64"),
+  (zed::Os::Mac, zed::Architecture::Aarch64, "darwin", "arm64"),
+  (
+    zed::Os::Windows,
+    zed::Architecture::X8664,
+    "windows",
+    "amd64",
+  ),
+  (
+    zed::Os::Windows,
+    zed::Architecture::Aarch64,
+    "windows",
+    "arm64",
+  ),
+];
+
+// e.g. ["linux/amd64", "linux/arm64", ...]
+fn supported_platforms() -> Vec<String> {
+  SUPPORTED_PLATFORMS
+    .iter()
+    .map(|(_, _, os, arch)| format!("{}/{}", os, arch))
+    .collect()
+}
+
 fn platform() -> zed::Result<(&'static str, &'static str)> {
   let (os, arch) = zed::current_platform();
-  let os_str = match os {
-    zed::Os::Linux => "linux",
-    zed::Os::Mac => "darwin",
-    zed::Os::Windows => "windows",
+  platform_for(os, arch)
+}
+
+fn platform_for(os: zed::Os, arch: zed::Architecture) -> zed::Result<(&'static str, &'static str)> {
+  SUPPORTED_PLATFORMS
+    .iter()
+    .find(|(supported_os, supported_arch, _, _)| *supported_os == os && *supported_arch == arch)
+    .map(|(_, _, os, arch)| (*os, *arch))
+    // Without a release asset to go by there's no install directory either, but PATH still works.
+    .ok_or_else(|| {
+      format!(
+        "MPLS doesn't publish builds for {:?} on {:?} (supported: {}). Build mpls for this machine and put it on your PATH, or point lsp.mpls.binary.path at it.",
+        arch,
+        os,
+        supported_platforms().join(", ")
+      )
+    })
+}
+
+// Where a language server binary came from. Shown by /mpls-doctor.
+#[derive(Debug, Clone, Copy)]
+enum Source {
+  Path,
+  Configured,
+  Activated,
+  Installed,
+  Downloaded,
+  Offline,
+  RolledBack,
+}
+
+impl std::fmt::Display for Source {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(match self {
+      Source::Path => "found on PATH",
+      Source::Configured => "set in lsp.mpls.binary.path",
+      Source::Activated => "activated with /mpls-use",
+      Source::Installed => "already installed",
+      Source::Downloaded => "downloaded",
+      Source::Offline => "installed, picked offline",
+      Source::RolledBack => "previous version, the newer one failed its check",
+    })
+  }
+}
+
+#[derive(Debug, Clone)]
+struct Resolved {
+  path: String,
+  source: Source,
+}
+
+impl Resolved {
+  fn new(path: String, source: Source) -> Self {
+    Self { path, source }
+  }
+}
+
+// worktree.which() may hand back a relative path, which would be resolved against whatever the
+// current directory is when Zed spawns the server. Anchor it to the worktree root instead, and give up
+// on it (so that we fall through to a managed install) if that still doesn't produce an absolute path.
+fn absolute_path_binary(worktree: &zed::Worktree, path: String) -> Option<String> {
+  if binary::is_absolute_host_path(&path) {
+    return Some(path);
+  }
+  let root_path = worktree.root_path();
+  let anchored = format!(
+    "{}/{}",
+    root_path.trim_end_matches(['/', '\\']),
+    path.trim_start_matches("./")
+  );
+  if binary::is_absolute_host_path(&anchored) {
+    log!("{} on PATH is relative, using {}", path, anchored);
+    Some(anchored)
+  } else {
+    log!(
+      "Ignoring mpls on PATH at {} because it isn't an absolute path",
+      path
+    );
+    None
+  }
+}
+
+// A pinned version applies to mpls on PATH too, as far as the binary tells us its version.
+// worktree.which() only ever returns the first match, so there are no other candidates to try.
+fn path_binary_satisfies_pin(path: &str, settings: &MplsSettings) -> bool {
+  let Some(pinned_version) = settings.pinned_version() else {
+    return true;
   };
-  let arch_str = match arch {
-    zed::Architecture::Aarch64 => "arm64",
-    zed::Architecture::X8664 => "amd64",
-    _ => return Err(format!("{:?} is not supported by MPLS", arch)),
+  match binary::reported_version(path) {
+    Some(version) if version == pinned_version => true,
+    Some(version) => {
+      log!(
+        "Ignoring {} on PATH because it's MPLS {}, but `{}` pins {}",
+        path,
+        version,
+        settings.pin_setting_name(),
+        pinned_version
+      );
+      false
+    }
+    None => {
+      log!("Can't tell which version {} is, using it anyway", path);
+      true
+    }
+  }
+}
+
+// lsp.mpls.binary.path, anchored to the worktree root if it's relative. Unlike a binary on PATH it's
+// never passed over, so one that doesn't exist is an error rather than a reason to look elsewhere.
+// It's outside the sandbox, so running it is the only way to tell. Like check_runs, any other reason
+// it can't be run lets it through, since it may still start fine as the language server.
+fn configured_binary(worktree: &zed::Worktree, path: &str) -> zed::Result<String> {
+  let path = if binary::is_absolute_host_path(path) {
+    path.to_string()
+  } else {
+    format!(
+      "{}/{}",
+      worktree.root_path().trim_end_matches(['/', '\\']),
+      path.trim_start_matches("./")
+    )
   };
-  Ok((os_str, arch_str))
+  match zed::process::Command::new(&path).arg("--version").output() {
+    Ok(_) => Ok(path),
+    Err(err) if binary::is_not_runnable(&err) => Err(format!(
+      "lsp.mpls.binary.path is set to {}, but it can't be run: {}",
+      path, err
+    )),
+    Err(err) => {
+      log!("Can't run {} to check it, using it anyway: {}", path, err);
+      Ok(path)
+    }
+  }
+}
+
+struct Cached {
+  resolved: Resolved,
+  // MplsSettings::resolution_key() at the time of resolution.
+  resolution_key: String,
 }
 
 struct Mpls {
-  language_server_path: Option<String>,
+  // Keyed by worktree id, so that worktrees with different settings don't clobber each other.
+  // Behind a Mutex because slash commands only get &self but still need to invalidate it.
+  language_server_paths: Mutex<HashMap<u64, Cached>>,
 }
 
 impl Mpls {
-  // Makes self.language_server_path not None
+  // Starts out with these worktrees already resolved, as if earlier launches had cached them.
+  #[cfg(test)]
+  fn with_cached<'a>(entries: impl IntoIterator<Item = (u64, &'a str, &'a str)>) -> Self {
+    let paths = entries
+      .into_iter()
+      .map(|(worktree_id, path, resolution_key)| {
+        let cached = Cached {
+          resolved: Resolved::new(path.to_string(), Source::Installed),
+          resolution_key: resolution_key.to_string(),
+        };
+        (worktree_id, cached)
+      })
+      .collect();
+    Self {
+      language_server_paths: Mutex::new(paths),
+    }
+  }
+
+  fn language_server_paths(&self) -> MutexGuard<'_, HashMap<u64, Cached>> {
+    // The map stays consistent even if a holder panicked, so a poisoned lock is fine to reuse.
+    self
+      .language_server_paths
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner)
+  }
+
   fn find_language_server(
-    &mut self,
+    &self,
+    language_server_id: &zed::LanguageServerId,
+    worktree: &zed::Worktree,
+    settings: &MplsSettings,
+  ) -> zed::Result<String> {
+    // Once resolved, the path is reused without any discovery or network work,
+    // until /mpls-use or /mpls-update clears the cache, the binary disappears,
+    // or a setting that decides which binary is picked changes.
+    let resolution_key = settings.resolution_key();
+    if let Some(path) = self.cached_path(worktree.id(), &resolution_key) {
+      return Ok(path);
+    }
+
+    let resolved = self.resolve_language_server(language_server_id, worktree, settings)?;
+    binary::check_arch(&resolved.path)?;
+    // Installation statuses can't carry text on success, so the log is where this shows up.
+    log!("Using {} ({})", resolved.path, resolved.source);
+    let path = resolved.path.clone();
+    self.language_server_paths().insert(
+      worktree.id(),
+      Cached {
+        resolved,
+        resolution_key,
+      },
+    );
+    Ok(path)
+  }
+
+  // The path resolved earlier for this worktree, if it's still usable. Otherwise its entry is dropped.
+  fn cached_path(&self, worktree_id: u64, resolution_key: &str) -> Option<String> {
+    let mut paths = self.language_server_paths();
+    let cached = paths.get(&worktree_id)?;
+    let path = cached.resolved.path.clone();
+    // Downloads are relative to the work directory, where we can check them. Anything else,
+    // e.g. a binary on PATH, is outside the extension's sandbox, so we have to trust it.
+    // The host may be Windows, whose absolute paths the sandbox's Path doesn't recognize.
+    let exists = binary::is_absolute_host_path(&path) || matches!(fs::exists(&path), Ok(true));
+    if exists && cached.resolution_key == resolution_key {
+      return Some(path);
+    }
+    if exists {
+      log!("Settings that pick the MPLS binary changed, resolving MPLS again");
+    } else {
+      log!("{} disappeared, resolving MPLS again", path);
+    }
+    paths.remove(&worktree_id);
+    None
+  }
+
+  fn resolve_language_server(
+    &self,
     language_server_id: &zed::LanguageServerId,
     worktree: &zed::Worktree,
-  ) -> zed::Result<()> {
-    // Do nothing if the language server is already installed.
-    if self.language_server_path.is_some() {
-      return Ok(());
+    settings: &MplsSettings,
+  ) -> zed::Result<Resolved> {
+    // An explicit binary.path beats everything, including the network.
+    if let Some(path) = &settings.binary_path {
+      return configured_binary(worktree, path).map(|path| Resolved::new(path, Source::Configured));
     }
 
-    if let Some(path) = worktree.which("mpls") {
-      self.language_server_path.replace(path);
-      return Ok(());
+    // After that, a version picked with /mpls-use beats everything else, and /mpls-use clears the cache
+    // so that switching takes effect on the next restart of the language server.
+    if let Some(version) = Metadata::load().active_version {
+      let naming = AssetNaming::for_platform(settings)?;
+      let installation = installs::installations(&naming)?
+        .into_iter()
+        .find(|installation| installation.version_string() == version)
+        .ok_or(format!(
+          "MPLS {} was activated with /mpls-use but it's no longer installed. Run /mpls-use latest to go back to automatic resolution.",
+          version
+        ))?;
+      let executable_path = installation.executable_path();
+      binary::make_executable(&executable_path)?;
+      return Ok(Resolved::new(executable_path, Source::Activated));
+    }
+
+    if settings.use_path_binary
+      && let Some(path) = worktree
+        .which(&settings.binary_name)
+        .and_then(|path| absolute_path_binary(worktree, path))
+      && path_binary_satisfies_pin(&path, settings)
+    {
+      return Ok(Resolved::new(path, Source::Path));
+    }
+
+    // Nothing can be downloaded or found offline without a release asset for this platform.
+    if let Err(err) = platform() {
+      return match settings.platform_fallback {
+        PlatformFallback::Error => Err(err),
+        PlatformFallback::Path => worktree
+          .which(&settings.binary_name)
+          .and_then(|path| absolute_path_binary(worktree, path))
+          .map(|path| Resolved::new(path, Source::Path))
+          .ok_or(format!(
+            "{} platform_fallback is \"path\", but {} isn't on it.",
+            err, settings.binary_name
+          )),
+      };
+    }
+
+    if settings.offline_only {
+      return self.when_offline(settings, OfflineCause::OfflineOnly);
+    }
+    if settings.wants_installed_latest() {
+      return self.when_offline(settings, OfflineCause::InstalledLatest);
+    }
+
+    // Don't attempt a download that can only fail with a cascade of permission errors.
+    if installs::work_dir_is_read_only() {
+      return self.when_offline(settings, OfflineCause::ReadOnlyWorkDir);
+    }
+
+    // /mpls-update skips the probe, so a false negative here can still be worked around by hand.
+    if settings.network_probe
+      && let Err(err) = release::probe_github(settings.user_agent.as_deref())
+    {
+      log!("{}", err);
+      return self.when_offline(settings, OfflineCause::NetworkFailed(&err));
     }
 
     // Check for updates.
@@ -43,127 +367,573 @@ impl Mpls {
       language_server_id,
       &zed::LanguageServerInstallationStatus::CheckingForUpdate,
     );
-    let release = zed::latest_github_release(
-      "mhersson/mpls",
-      zed::GithubReleaseOptions {
-        require_assets: true,
-        pre_release: false,
-      },
-    );
-    if let Ok(release) = release {
+    let err = match fetch_release(settings) {
       // If we have internet connection
-      self.when_online(&release, language_server_id)
-    } else {
+      Ok(release) => {
+        record_latest_version(settings, &release);
+        // A transient failure further along, e.g. a 403 on the download, shouldn't cost
+        // the user a working install they already have.
+        return self
+          .when_online(&release, Some(language_server_id), Some(worktree), settings)
+          .or_else(|err| {
+            if checksums::is_mismatch(&err) {
+              return Err(err);
+            }
+            let resolved = self.when_offline(settings, OfflineCause::InstallFailed(&err))?;
+            log!(
+              "Installing MPLS {} failed, using {}: {}",
+              release.version,
+              resolved.path,
+              err
+            );
+            Ok(resolved)
+          });
+      }
+      Err(err) => err,
+    };
+
+    match (LookupFailure::classify(&err), settings.pinned_tag()) {
+      (LookupFailure::NotFound, Some(tag)) => Err(format!(
+        "mpls release {} not found in {} releases. Check the `{}` setting for a typo.",
+        tag,
+        MPLS_REPO,
+        settings.pin_setting_name()
+      )),
+      (LookupFailure::RateLimited, _) => {
+        self.when_offline(settings, OfflineCause::RateLimited(&err))
+      }
       // If we don't
-      self.when_offline()
+      _ => self.when_offline(settings, OfflineCause::NetworkFailed(&err)),
     }
   }
 
+  // language_server_id is None when there is no language server to report the status to, e.g. in /mpls-update.
   fn when_online(
-    &mut self,
+    &self,
     release: &GithubRelease,
-    language_server_id: &zed::LanguageServerId,
-  ) -> zed::Result<()> {
-    let (os, arch) = platform()?;
-    let file_type = match os {
-      "windows" => zed::DownloadedFileType::Zip,
-      "linux" | "darwin" => zed::DownloadedFileType::GzipTar,
-      _ => unreachable!("There's a bug in the codebase"),
-    };
-    let file_type_str = match file_type {
-      zed::DownloadedFileType::Zip => "zip",
-      zed::DownloadedFileType::GzipTar => "tar.gz",
-      zed::DownloadedFileType::Gzip => "gz",
-      zed::DownloadedFileType::Uncompressed => "",
+    language_server_id: Option<&zed::LanguageServerId>,
+    worktree: Option<&zed::Worktree>,
+    settings: &MplsSettings,
+  ) -> zed::Result<Resolved> {
+    let naming = AssetNaming::for_platform(settings)?;
+    let version = release_version(release, settings)?;
+    let unarchived_asset_name = naming.unarchived(&version);
+    let executable_path = naming.executable_path(&unarchived_asset_name);
+
+    // Remember which settings produced each install, so that changing them gets the intended asset
+    // instead of the stale one sitting in the same directory.
+    let mut metadata = Metadata::load();
+    let asset_identity = settings.asset_identity();
+    let is_stale = is_stale_install(&metadata, &unarchived_asset_name, settings);
+    let installed = fs::exists(&executable_path);
+    let republished_digest = match (
+      &installed,
+      metadata.asset_digests.get(&unarchived_asset_name),
+    ) {
+      (Ok(true), Some(recorded)) if !is_stale => {
+        republished_digest(release, &naming, &version, settings, recorded)
+      }
+      _ => None,
     };
-    let archived_asset_name = format!(
-      "mpls_{}_{}_{}.{}",
-      &release.version[1..], // v0.16.0 -> 0.16.0
-      os,
-      arch,
-      file_type_str
-    );
-    let unarchived_asset_name = format!(
-      "mpls_{}_{}_{}",
-      &release.version[1..], // v0.16.0 -> 0.16.0
-      os,
-      arch
-    );
-    let executable_path = format!("{}/{}", unarchived_asset_name, "mpls");
 
-    if let Ok(true) = fs::exists(&executable_path) {
-      // The language server is already downloaded.
-      zed::make_file_executable(&executable_path)?;
-      self.language_server_path.replace(executable_path);
-      return Ok(());
+    let is_failed = metadata.failed_installs.contains(&unarchived_asset_name);
+    match existing_install(installed, is_stale, is_failed, republished_digest) {
+      Existing::Republished(digest) => {
+        log!(
+          "MPLS {} was re-published with different assets (SHA-256 {} instead of {}), downloading it again",
+          release.version,
+          digest,
+          metadata.asset_digests[&unarchived_asset_name]
+        );
+        let _ = fs::remove_dir_all(&unarchived_asset_name);
+      }
+      Existing::Failed => {
+        let err = format!(
+          "MPLS {} failed its check when it was downloaded. Remove {} to download it again.",
+          release.version, unarchived_asset_name
+        );
+        return roll_back(&naming, metadata, settings, unarchived_asset_name, err);
+      }
+      Existing::Reuse => {
+        // The language server is already downloaded.
+        binary::make_executable(&executable_path)?;
+        return Ok(Resolved::new(executable_path, Source::Installed));
+      }
+      Existing::Stale => {
+        log!(
+          "{} was installed with different asset settings, downloading it again",
+          unarchived_asset_name
+        );
+        let _ = fs::remove_dir_all(&unarchived_asset_name);
+      }
+      Existing::Absent => {}
+      Existing::Unreadable(err) => {
+        let err = format!(
+          "Can't check whether {} is already installed: {}",
+          executable_path, err
+        );
+        log!("{}", err);
+        return Err(err);
+      }
     }
 
     // If there was an update, we download the new language server.
-    let asset = release
-      .assets
-      .iter()
-      .find(|asset| asset.name == archived_asset_name)
-      .ok_or(format!("Can't find the executable in MPLS GitHub release."))?;
-    zed::set_language_server_installation_status(
-      language_server_id,
-      &zed::LanguageServerInstallationStatus::Downloading,
+    if let Some(err) = assets::not_uploaded_yet(release, settings) {
+      log!("{}", err);
+      return Err(err);
+    }
+    let (asset, file_type) =
+      assets::find_preferred_asset(&release.assets, &naming, &version, settings).map_err(
+        |tried| {
+          let asset_names: Vec<&str> = release
+            .assets
+            .iter()
+            .map(|asset| asset.name.as_str())
+            .collect();
+          let err = format!(
+            "Can't find {} in the assets of MPLS release {}. The release has: {}. {}",
+            tried.join(" or "),
+            release.version,
+            if asset_names.is_empty() {
+              "no assets".to_string()
+            } else {
+              asset_names.join(", ")
+            },
+            offline::manual_install_hint(&naming, &version)
+          );
+          log!("{}", err);
+          err
+        },
+      )?;
+    if let Some(language_server_id) = language_server_id {
+      zed::set_language_server_installation_status(
+        language_server_id,
+        &zed::LanguageServerInstallationStatus::Downloading,
+      );
+    }
+    let expected = if let Some(digest) = &settings.asset_sha256 {
+      Some(checksums::pinned(asset, digest))
+    } else if settings.verify_checksums {
+      retry::retry(
+        settings.download_retries,
+        settings.retry_interval_ms,
+        |err| !installs::is_out_of_space(err),
+        |_| checksums::expected(release, asset, settings.user_agent.as_deref()),
+      )?
+    } else {
+      None
+    };
+    let downloaded = retry::retry(
+      settings.download_retries,
+      settings.retry_interval_ms,
+      is_retryable_download,
+      |attempt| {
+        if attempt > 0 {
+          // Don't let a partial extraction from the failed attempt get in the way.
+          let _ = fs::remove_dir_all(&unarchived_asset_name);
+        }
+        // Zed's installation status can't carry text, so the attempts only show up in the log.
+        log!(
+          "Downloading {} (attempt {} of {})",
+          asset.download_url,
+          attempt + 1,
+          settings.download_retries + 1
+        );
+        archive::install(
+          &asset.download_url,
+          file_type,
+          &unarchived_asset_name,
+          &executable_path,
+          expected.as_ref(),
+          settings.user_agent.as_deref(),
+        )
+      },
     );
-    zed::download_file(&asset.download_url, &unarchived_asset_name, file_type)?;
+    if let Err(err) = downloaded {
+      // A partial extraction would otherwise look like an installation to the offline scan.
+      let _ = fs::remove_dir_all(&unarchived_asset_name);
+      if installs::is_out_of_space(&err) {
+        return Err(format!(
+          "Not enough disk space to install MPLS {}. Free up some space and restart the language server. ({})",
+          version, err
+        ));
+      }
+      if checksums::is_mismatch(&err) {
+        return Err(err);
+      }
+      return Err(format!(
+        "Downloading MPLS {} failed: {} (asset {} from {})",
+        version, err, asset.name, asset.download_url
+      ));
+    }
 
-    zed::make_file_executable(&executable_path)?;
-    self.language_server_path.replace(executable_path);
+    if let Err(err) =
+      installs::normalize_layout(&unarchived_asset_name, &naming.executable_subpath())
+    {
+      // An install without its binary would still look like one to the offline scan.
+      let _ = fs::remove_dir_all(&unarchived_asset_name);
+      return Err(format!("Installing MPLS {} failed: {}", version, err));
+    }
+    binary::make_executable(&executable_path)?;
+    if let Some(post_install_command) = &settings.post_install_command
+      && let Err(err) = hooks::run_post_install(post_install_command, worktree, &executable_path)
+    {
+      // Otherwise the fast path above would happily launch the rejected binary next time.
+      let _ = fs::remove_dir_all(&unarchived_asset_name);
+      return Err(err);
+    }
 
-    Ok(())
-  }
+    if settings.verify_binary
+      && let Err(err) = binary::check_runs(&executable_path)
+    {
+      let err = format!("Verifying MPLS {} failed: {}", version, err);
+      return roll_back(&naming, metadata, settings, unarchived_asset_name, err);
+    }
 
-  fn when_offline(&mut self) -> zed::Result<()> {
-    let (os, arch) = platform()?;
-    let unarchived_asset_pattern = format!(r"^mpls_([0-9]+)\.([0-9]+)\.([0-9]+)_{}_{}$", os, arch);
-    let unarchived_asset_regex = Regex::new(&unarchived_asset_pattern).unwrap();
+    if settings.dedupe_installs {
+      installs::dedupe(
+        &naming,
+        &unarchived_asset_name,
+        &executable_path,
+        &mut metadata.checksums,
+      );
+    }
+    if settings.clean_install {
+      self.remove_other_installs(&naming, &mut metadata, &unarchived_asset_name);
+    }
+    metadata
+      .failed_installs
+      .retain(|dir_name| *dir_name != unarchived_asset_name);
+    metadata.last_good_version = Some(version);
+    match &expected {
+      Some(expected) => metadata
+        .asset_digests
+        .insert(unarchived_asset_name.clone(), expected.digest().to_string()),
+      None => metadata.asset_digests.remove(&unarchived_asset_name),
+    };
+    metadata
+      .asset_identities
+      .insert(unarchived_asset_name, asset_identity);
+    if let Err(err) = metadata.save() {
+      // Only costs a redundant download if the settings change later.
+      log!("{}", err);
+    }
+    Ok(Resolved::new(executable_path, Source::Downloaded))
+  }
 
-    let mut version_triples: Vec<(usize, usize, usize)> = Vec::new();
-    for dir in current_dir()
-      .and_then(fs::read_dir)
-      .map_err(|err| err.to_string())?
-    {
-      let dir = dir.map_err(|err| err.to_string())?;
-      if !dir.file_type().map_err(|err| err.to_string())?.is_dir() {
+  // For clean_install. Runs only once the new version is in place, so a failed download never
+  // leaves nothing behind, and spares binaries that language servers are running right now, as well
+  // as the version activated with /mpls-use and the one a failed upgrade would roll back to.
+  fn remove_other_installs(&self, naming: &AssetNaming, metadata: &mut Metadata, keep: &str) {
+    let running: Vec<String> = self
+      .language_server_paths()
+      .values()
+      .map(|cached| cached.resolved.path.clone())
+      .collect();
+    let Ok(installations) = installs::installations(naming) else {
+      return;
+    };
+    for installation in installations {
+      let dir_name = &installation.dir_name;
+      let version = Some(installation.version_string());
+      if dir_name == keep
+        || metadata.active_version == version
+        || metadata.last_good_version == version
+        || running
+          .iter()
+          .any(|path| path.starts_with(&format!("{}/", dir_name)))
+      {
         continue;
       }
+      match fs::remove_dir_all(dir_name) {
+        Ok(()) => {
+          log!("clean_install removed {}", dir_name);
+          metadata.forget_install(dir_name);
+        }
+        Err(err) => log!("clean_install can't remove {}: {}", dir_name, err),
+      }
+    }
+  }
 
-      let dirname = dir.file_name();
-      let dirname = dirname
-        .to_str()
-        .ok_or("dirname contains invalid UTF-8 string")?;
+  fn when_offline(&self, settings: &MplsSettings, cause: OfflineCause) -> zed::Result<Resolved> {
+    let naming = AssetNaming::for_platform(settings)?;
+    let executable_path = offline_executable(settings, &naming)?
+      .ok_or_else(|| offline::no_installation_error(&naming, settings.pinned_version(), cause))?;
 
-      if let Some(captures) = unarchived_asset_regex.captures(dirname) {
-        // It's safe to unwrap here because [0-9] only captures ASCII digits. parse() never panics.
-        version_triples.push((
-          captures[1].parse().unwrap(),
-          captures[2].parse().unwrap(),
-          captures[3].parse().unwrap(),
-        ));
+    binary::make_executable(&executable_path)?;
+    Ok(Resolved::new(executable_path, Source::Offline))
+  }
+}
+
+// What's in the version directory a release would be installed into.
+#[derive(Debug, PartialEq, Eq)]
+enum Existing {
+  Absent,
+  Reuse,
+  // Failed its check when it was downloaded, so it's rolled back from rather than used.
+  Failed,
+  // Made with other asset settings, so it's replaced.
+  Stale,
+  // The release was re-published with an asset of this SHA-256, so it's replaced.
+  Republished(String),
+  // If we can't even look at the path, a download into it would fail in a more confusing way.
+  Unreadable(String),
+}
+
+fn existing_install(
+  exists: std::io::Result<bool>,
+  is_stale: bool,
+  is_failed: bool,
+  republished_digest: Option<String>,
+) -> Existing {
+  match exists {
+    Ok(false) => Existing::Absent,
+    Err(err) => Existing::Unreadable(err.to_string()),
+    Ok(true) if is_stale => Existing::Stale,
+    Ok(true) if let Some(digest) = republished_digest => Existing::Republished(digest),
+    Ok(true) if is_failed => Existing::Failed,
+    Ok(true) => Existing::Reuse,
+  }
+}
+
+// Whether the install in `dir_name` was made with settings that would pick a different asset. One
+// without a record, e.g. extracted by hand, only counts when asset_sha256 pins bytes it can't vouch for.
+fn is_stale_install(metadata: &Metadata, dir_name: &str, settings: &MplsSettings) -> bool {
+  let other_identity = metadata
+    .asset_identities
+    .get(dir_name)
+    .is_some_and(|recorded| *recorded != settings.asset_identity());
+  let misses_pin = settings.asset_sha256.as_ref().is_some_and(|pin| {
+    metadata
+      .asset_digests
+      .get(dir_name)
+      .is_none_or(|recorded| !recorded.eq_ignore_ascii_case(pin))
+  });
+  other_identity || misses_pin
+}
+
+// The SHA-256 GitHub or the release's checksums file lists now for the asset a version directory was installed
+// from, when it's not the `recorded` one, i.e. the tag was re-published with different assets.
+// Only with verify_checksums: an asset_sha256 pin already decides in is_stale_install().
+fn republished_digest(
+  release: &GithubRelease,
+  naming: &AssetNaming,
+  version: &str,
+  settings: &MplsSettings,
+  recorded: &str,
+) -> Option<String> {
+  if !settings.verify_checksums || settings.asset_sha256.is_some() {
+    return None;
+  }
+  let (asset, _) = assets::find_preferred_asset(&release.assets, naming, version, settings).ok()?;
+  match checksums::expected(release, asset, settings.user_agent.as_deref()) {
+    Ok(Some(expected)) if expected.digest() != recorded => Some(expected.digest().to_string()),
+    Ok(_) => None,
+    Err(err) => {
+      log!(
+        "Can't tell whether MPLS {} was re-published, using the installed one: {}",
+        release.version,
+        err
+      );
+      None
+    }
+  }
+}
+
+// Marks a download that doesn't run as failed and goes back to the version that last worked,
+// or failing that, the newest other one that's installed.
+fn roll_back(
+  naming: &AssetNaming,
+  mut metadata: Metadata,
+  settings: &MplsSettings,
+  dir_name: String,
+  err: String,
+) -> zed::Result<Resolved> {
+  log!("{}", err);
+  if !metadata.failed_installs.contains(&dir_name) {
+    metadata.failed_installs.push(dir_name);
+    if let Err(err) = metadata.save() {
+      log!("{}", err);
+    }
+  }
+
+  // A pin allows no other version, so it can only roll back to another install of that version.
+  let pinned = settings.pinned_version();
+  let installations: Vec<installs::Installation> = installs::installations(naming)?
+    .into_iter()
+    .filter(|installation| !metadata.failed_installs.contains(&installation.dir_name))
+    .filter(|installation| {
+      pinned
+        .as_ref()
+        .is_none_or(|version| installation.version_string() == *version)
+    })
+    .collect();
+  if installations.is_empty()
+    && let Some(version) = &pinned
+  {
+    return Err(format!(
+      "{} MPLS is pinned to {} by the `{}` setting, so it won't roll back to another version.",
+      err,
+      version,
+      settings.pin_setting_name()
+    ));
+  }
+  let previous = installations
+    .iter()
+    .find(|installation| {
+      metadata.last_good_version.as_ref() == Some(&installation.version_string())
+    })
+    .or_else(|| {
+      installations
+        .iter()
+        .rev()
+        .find(|installation| settings.channel.accepts(&installation.version))
+    })
+    .ok_or(format!(
+      "{} There's no earlier version to roll back to.",
+      err
+    ))?;
+
+  log!("Rolled back to MPLS {}", previous.version_string());
+  let executable_path = previous.executable_path();
+  binary::make_executable(&executable_path)?;
+  Ok(Resolved::new(executable_path, Source::RolledBack))
+}
+
+// The installed binary when_offline falls back to, if any.
+fn offline_executable(
+  settings: &MplsSettings,
+  naming: &AssetNaming,
+) -> zed::Result<Option<String>> {
+  let installations = retry::retry(
+    settings.scan_retries,
+    settings.retry_interval_ms,
+    |_| true,
+    |_| installs::installations(naming),
+  )?;
+  let failed_installs = Metadata::load().failed_installs;
+  let picked = pick_offline(&installations, &failed_installs, settings);
+  let executable_path = match settings.pinned_version() {
+    Some(version) => picked
+      .map(installs::Installation::executable_path)
+      .or_else(|| {
+        // A release_tag that isn't semver never shows up in the scan, but its directory may still be there.
+        let dir_name = naming.unarchived(version);
+        let executable_path = naming.executable_path(&dir_name);
+        (matches!(fs::exists(&executable_path), Ok(true)) && !failed_installs.contains(&dir_name))
+          .then_some(executable_path)
+      }),
+    None => picked.map(installs::Installation::executable_path),
+  };
+  Ok(executable_path)
+}
+
+// The installation to use offline, from ones sorted oldest first: the pinned version, or else the
+// newest one the channel accepts. Installs that failed their check are never picked.
+fn pick_offline<'a>(
+  installations: &'a [installs::Installation],
+  failed_installs: &[String],
+  settings: &MplsSettings,
+) -> Option<&'a installs::Installation> {
+  let mut usable = installations
+    .iter()
+    .filter(|installation| !failed_installs.contains(&installation.dir_name));
+  match settings.pinned_version() {
+    Some(version) => usable.find(|installation| installation.version_string() == version),
+    None => usable
+      .rev()
+      .find(|installation| settings.channel.accepts(&installation.version)),
+  }
+}
+
+// Remembers the latest release for /mpls-doctor. Pinned lookups say nothing about what's latest.
+fn record_latest_version(settings: &MplsSettings, release: &GithubRelease) {
+  if settings.pinned_tag().is_some() || settings.asset_sha256.is_some() {
+    return;
+  }
+  let Ok(version) = release_version(release, settings) else {
+    return;
+  };
+  let mut metadata = Metadata::load();
+  metadata
+    .latest_versions
+    .insert(settings.channel.as_str().to_string(), version);
+  if let Err(err) = metadata.save() {
+    log!("{}", err);
+  }
+}
+
+// Looks up the pinned release, or the latest one on the configured channel.
+fn fetch_release(settings: &MplsSettings) -> zed::Result<GithubRelease> {
+  match settings.pinned_tag() {
+    Some(tag) => zed::github_release_by_tag_name(MPLS_REPO, &tag),
+    None => {
+      // An asset_sha256 pin without a release_tag or version belongs to whichever release has it.
+      if let Some(digest) = &settings.asset_sha256 {
+        match release::tag_with_digest(MPLS_REPO, digest, settings.user_agent.as_deref()) {
+          Ok(Some(tag)) => return zed::github_release_by_tag_name(MPLS_REPO, &tag),
+          Ok(None) => log!(
+            "No release of {} has an asset with SHA-256 {}, trying the latest one",
+            MPLS_REPO,
+            digest
+          ),
+          Err(err) => log!(
+            "Can't search the releases of {} for asset_sha256, trying the latest one: {}",
+            MPLS_REPO,
+            err
+          ),
+        }
       }
+      let release = zed::latest_github_release(MPLS_REPO, settings.release_options())?;
+      if settings.require_assets {
+        log_newer_tag(&release, settings);
+      }
+      Ok(release)
     }
+  }
+}
 
-    version_triples.sort();
-    let latest_installed_version = version_triples.last().ok_or("No installation of MPLS has found. We can't install it because we have no internet connection.")?;
-    let executable_path = format!(
-      "mpls_{}.{}.{}_{}_{}/mpls",
-      latest_installed_version.0, latest_installed_version.1, latest_installed_version.2, os, arch
-    );
+// A newer tag than the latest release with assets is most likely a release whose binaries are still
+// being uploaded. Using the older one is right, but it deserves an explanation.
+fn log_newer_tag(release: &GithubRelease, settings: &MplsSettings) {
+  let options = settings.release_options();
+  match release::newer_tag(
+    MPLS_REPO,
+    &release.version,
+    options.pre_release,
+    settings.user_agent.as_deref(),
+  ) {
+    Ok(Some(tag)) => log!(
+      "MPLS {} is tagged, but its binaries haven't been uploaded yet. Using {} until they are.",
+      tag,
+      release.version
+    ),
+    Ok(None) => {}
+    Err(err) => log!("Can't look up the tags of {}: {}", MPLS_REPO, err),
+  }
+}
 
-    zed::make_file_executable(&executable_path)?;
-    self.language_server_path.replace(executable_path);
+// Trying again won't free up any disk space, and won't change what a checksum says.
+fn is_retryable_download(err: &str) -> bool {
+  !installs::is_out_of_space(err) && !checksums::is_mismatch(err)
+}
 
-    Ok(())
+// The version as it appears in asset names. release.version stays the full tag, for messages.
+// A release_tag without a version in it, e.g. "nightly", is used as it is.
+fn release_version(release: &GithubRelease, settings: &MplsSettings) -> zed::Result<String> {
+  match version::asset_version(&release.version) {
+    Ok(version) => Ok(version.to_string()),
+    Err(_) if settings.release_tag.as_ref() == Some(&release.version) => {
+      Ok(release.version.clone())
+    }
+    Err(err) => Err(err),
   }
 }
 
 impl zed::Extension for Mpls {
   fn new() -> Self {
     Self {
-      language_server_path: None,
+      language_server_paths: Mutex::new(HashMap::new()),
     }
   }
 
@@ -172,21 +942,328 @@ impl zed::Extension for Mpls {
     language_server_id: &zed::LanguageServerId,
     worktree: &zed::Worktree,
   ) -> zed::Result<zed::Command> {
-    if let Err(err) = self.find_language_server(language_server_id, worktree) {
-      zed::set_language_server_installation_status(
-        language_server_id,
-        &zed::LanguageServerInstallationStatus::Failed(err.to_string()),
-      );
-      return Err(err);
-    } else {
-      zed::set_language_server_installation_status(
-        language_server_id,
-        &zed::LanguageServerInstallationStatus::None,
-      );
+    // Invalid settings are shown the same way as a failed install, rather than only in the log.
+    let resolution =
+      MplsSettings::for_worktree(language_server_id.as_ref(), worktree).and_then(|settings| {
+        let path = self.find_language_server(language_server_id, worktree, &settings)?;
+        Ok((path, settings))
+      });
+    let (path, settings) = match resolution {
+      Ok(resolution) => {
+        zed::set_language_server_installation_status(
+          language_server_id,
+          &zed::LanguageServerInstallationStatus::None,
+        );
+        resolution
+      }
+      Err(err) => {
+        zed::set_language_server_installation_status(
+          language_server_id,
+          &zed::LanguageServerInstallationStatus::Failed(err.to_string()),
+        );
+        return Err(err);
+      }
+    };
+
+    Ok(zed::Command::new(path).args(settings.arguments()))
+  }
+
+  fn complete_slash_command_argument(
+    &self,
+    command: zed::SlashCommand,
+    _args: Vec<String>,
+  ) -> zed::Result<Vec<zed::SlashCommandArgumentCompletion>> {
+    match command.name.as_str() {
+      "mpls-use" => commands::complete_use_version(),
+      _ => Ok(Vec::new()),
     }
+  }
 
-    Ok(zed::Command::new(self.language_server_path.as_ref().expect("This shouldn't happen. self.install_language_server() is supposed to make self.language_server_path not None")).arg("--enable-emoji").arg("--enable-wikilinks").arg("--enable-footnotes"))
+  fn run_slash_command(
+    &self,
+    command: zed::SlashCommand,
+    args: Vec<String>,
+    worktree: Option<&zed::Worktree>,
+  ) -> zed::Result<zed::SlashCommandOutput> {
+    match command.name.as_str() {
+      "mpls-doctor" => commands::doctor(self, worktree),
+      "mpls-use" => commands::use_version(self, &args, worktree),
+      "mpls-update" => commands::update(self, worktree),
+      "mpls-plan" => commands::plan(self, worktree),
+      "mpls-validate" => commands::validate(worktree),
+      "mpls-export" => commands::export(worktree),
+      "mpls-repair" => commands::repair(self, worktree),
+      name => Err(format!("Unknown slash command: {}", name)),
+    }
   }
 }
 
 zed::register_extension!(Mpls);
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn worktrees_dont_share_a_cached_path() {
+    let mpls = Mpls::with_cached([
+      (1, "/opt/mpls-0.15.0/mpls", "pinned"),
+      (2, "/usr/local/bin/mpls", "latest"),
+    ]);
+    assert_eq!(
+      mpls.cached_path(1, "pinned").as_deref(),
+      Some("/opt/mpls-0.15.0/mpls")
+    );
+    assert_eq!(
+      mpls.cached_path(2, "latest").as_deref(),
+      Some("/usr/local/bin/mpls")
+    );
+    assert_eq!(mpls.cached_path(3, "latest"), None);
+
+    // Settings changing in one worktree leaves the other's path alone.
+    assert_eq!(mpls.cached_path(2, "pinned"), None);
+    assert_eq!(
+      mpls.cached_path(1, "pinned").as_deref(),
+      Some("/opt/mpls-0.15.0/mpls")
+    );
+  }
+
+  #[test]
+  fn reuses_a_cached_path_until_the_settings_change() {
+    let mpls = Mpls::with_cached([(1, "/usr/local/bin/mpls", "key")]);
+    assert_eq!(
+      mpls.cached_path(1, "key").as_deref(),
+      Some("/usr/local/bin/mpls")
+    );
+    assert_eq!(
+      mpls.cached_path(1, "key").as_deref(),
+      Some("/usr/local/bin/mpls")
+    );
+    assert_eq!(mpls.cached_path(1, "other key"), None);
+    // The stale entry is gone, not just skipped.
+    assert_eq!(mpls.cached_path(1, "key"), None);
+  }
+
+  #[test]
+  fn trusts_host_paths_but_checks_downloads() {
+    let mpls = Mpls::with_cached([
+      (1, "C:\\Users\\me\\go\\bin\\mpls.exe", "key"),
+      (2, "\\\\server\\tools\\mpls.exe", "key"),
+      (3, "mpls_0.0.0_linux_amd64/mpls", "key"),
+    ]);
+    assert!(mpls.cached_path(1, "key").is_some());
+    assert!(mpls.cached_path(2, "key").is_some());
+    // Relative to the work directory, where it was never installed.
+    assert_eq!(mpls.cached_path(3, "key"), None);
+    assert!(mpls.language_server_paths().get(&3).is_none());
+  }
+
+  #[test]
+  fn retries_empty_downloads_but_not_mismatches() {
+    // A server that keeps sending an empty body.
+    let path = std::env::temp_dir().join("mpls-lib-empty");
+    let path = path.to_str().unwrap();
+    fs::write(path, "").unwrap();
+    let mut attempts = 0;
+    let err = retry::retry(2, 0, is_retryable_download, |_| {
+      attempts += 1;
+      archive::check_not_empty(path)
+    })
+    .unwrap_err();
+    let _ = fs::remove_file(path);
+    assert_eq!(attempts, 3);
+    assert!(err.contains("was empty"), "{}", err);
+
+    let mut attempts = 0;
+    let err = retry::retry(2, 0, is_retryable_download, |_| -> zed::Result<()> {
+      attempts += 1;
+      Err("Checksum mismatch: mpls_0.16.0_linux_amd64.tar.gz".to_string())
+    })
+    .unwrap_err();
+    assert_eq!(attempts, 1);
+    assert!(checksums::is_mismatch(&err));
+  }
+
+  #[test]
+  fn asset_sha256_needs_a_matching_recorded_digest() {
+    let dir_name = "mpls_0.16.0_linux_amd64";
+    let pin = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    let mut settings = MplsSettings::default();
+    let mut metadata = Metadata::default();
+    // Extracted by hand, with nothing recorded.
+    assert!(!is_stale_install(&metadata, dir_name, &settings));
+    settings.asset_sha256 = Some(pin.to_ascii_uppercase());
+    assert!(is_stale_install(&metadata, dir_name, &settings));
+
+    metadata
+      .asset_digests
+      .insert(dir_name.to_string(), "0".repeat(64));
+    assert!(is_stale_install(&metadata, dir_name, &settings));
+    metadata
+      .asset_digests
+      .insert(dir_name.to_string(), pin.to_string());
+    assert!(!is_stale_install(&metadata, dir_name, &settings));
+
+    metadata
+      .asset_identities
+      .insert(dir_name.to_string(), "other settings".to_string());
+    assert!(is_stale_install(&metadata, dir_name, &settings));
+  }
+
+  #[test]
+  fn supports_every_os_on_x86_64_and_arm64() {
+    for os in [zed::Os::Mac, zed::Os::Linux, zed::Os::Windows] {
+      for arch in [zed::Architecture::X8664, zed::Architecture::Aarch64] {
+        let (os_name, arch_name) = platform_for(os, arch).unwrap();
+        assert!(supported_platforms().contains(&format!("{}/{}", os_name, arch_name)));
+      }
+      let err = platform_for(os, zed::Architecture::X86).unwrap_err();
+      assert!(err.contains("linux/amd64"), "{}", err);
+    }
+    assert_eq!(supported_platforms().len(), SUPPORTED_PLATFORMS.len());
+    assert_eq!(
+      supported_platforms(),
+      [
+        "linux/amd64",
+        "linux/arm64",
+        "darwin/amd64",
+        "darwin/arm64",
+        "windows/amd64",
+        "windows/arm64"
+      ]
+    );
+  }
+
+  #[test]
+  fn decides_what_to_do_with_an_existing_install() {
+    use std::io::{Error, ErrorKind};
+
+    let denied = || Err(Error::from(ErrorKind::PermissionDenied));
+    assert!(matches!(
+      existing_install(denied(), false, false, None),
+      Existing::Unreadable(_)
+    ));
+    // Not even a stale install is deleted when its path can't be looked at.
+    assert!(matches!(
+      existing_install(denied(), true, true, None),
+      Existing::Unreadable(_)
+    ));
+    assert_eq!(
+      existing_install(Ok(false), true, true, None),
+      Existing::Absent
+    );
+    assert_eq!(
+      existing_install(Ok(true), false, false, None),
+      Existing::Reuse
+    );
+    assert_eq!(
+      existing_install(Ok(true), false, true, None),
+      Existing::Failed
+    );
+    assert_eq!(
+      existing_install(Ok(true), true, true, Some("digest".to_string())),
+      Existing::Stale
+    );
+    assert_eq!(
+      existing_install(Ok(true), false, true, Some("digest".to_string())),
+      Existing::Republished("digest".to_string())
+    );
+  }
+
+  fn pick(dir_names: &[&str], failed_installs: &[&str], settings: &MplsSettings) -> Option<String> {
+    let naming = AssetNaming::new(settings, "linux", "amd64");
+    let dir_names: Vec<String> = dir_names.iter().map(|name| name.to_string()).collect();
+    let failed_installs: Vec<String> = failed_installs
+      .iter()
+      .map(|name| name.to_string())
+      .collect();
+    let installations = installs::from_dir_names(&dir_names, &naming);
+    pick_offline(&installations, &failed_installs, settings)
+      .map(|installation| installation.dir_name.clone())
+  }
+
+  #[test]
+  fn picks_the_newest_installed_version_offline() {
+    let settings = MplsSettings::default();
+    assert_eq!(pick(&[], &[], &settings), None);
+    assert_eq!(
+      pick(
+        &[
+          "mpls_0.16.0_darwin_arm64",
+          "mpls_0.16.0_windows_amd64",
+          "mpls-metadata.json"
+        ],
+        &[],
+        &settings
+      ),
+      None
+    );
+    assert_eq!(
+      pick(
+        &[
+          "mpls_0.9.0_linux_amd64",
+          "mpls_0.16.0_linux_amd64",
+          "mpls-0.15.2"
+        ],
+        &[],
+        &settings
+      )
+      .as_deref(),
+      Some("mpls_0.16.0_linux_amd64")
+    );
+  }
+
+  #[test]
+  fn only_nightly_picks_pre_releases_offline() {
+    let dir_names = ["mpls_0.16.0_linux_amd64", "mpls_0.17.0-rc.1_linux_amd64"];
+    assert_eq!(
+      pick(&dir_names, &[], &MplsSettings::default()).as_deref(),
+      Some("mpls_0.16.0_linux_amd64")
+    );
+    let nightly = MplsSettings {
+      channel: settings::Channel::Nightly,
+      ..MplsSettings::default()
+    };
+    assert_eq!(
+      pick(&dir_names, &[], &nightly).as_deref(),
+      Some("mpls_0.17.0-rc.1_linux_amd64")
+    );
+  }
+
+  #[test]
+  fn honours_the_pin_offline() {
+    let dir_names = ["mpls_0.15.0_linux_amd64", "mpls_0.16.0_linux_amd64"];
+    let pinned = |version: &str| MplsSettings {
+      version: Some(version.to_string()),
+      ..MplsSettings::default()
+    };
+    assert_eq!(
+      pick(&dir_names, &[], &pinned("0.15.0")).as_deref(),
+      Some("mpls_0.15.0_linux_amd64")
+    );
+    // Never some other version instead.
+    assert_eq!(pick(&dir_names, &[], &pinned("0.14.0")), None);
+    assert_eq!(
+      pick(&dir_names, &["mpls_0.15.0_linux_amd64"], &pinned("0.15.0")),
+      None
+    );
+  }
+
+  #[test]
+  fn skips_a_broken_newest_offline() {
+    let dir_names = [
+      "mpls_0.14.0_linux_amd64",
+      "mpls_0.15.0_linux_amd64",
+      "mpls_0.16.0_linux_amd64",
+    ];
+    assert_eq!(
+      pick(
+        &dir_names,
+        &["mpls_0.16.0_linux_amd64"],
+        &MplsSettings::default()
+      )
+      .as_deref(),
+      Some("mpls_0.15.0_linux_amd64")
+    );
+  }
+}
@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, io::ErrorKind};
+use zed_extension_api::{self as zed, serde_json};
+
+// Lives next to the mpls_* version directories in the extension's work directory.
+const METADATA_PATH: &str = "mpls-metadata.json";
+
+// State that has to survive Zed restarts.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Metadata {
+  // Set by /mpls-use. When present, this installed version is launched instead of resolving one.
+  pub(crate) active_version: Option<String>,
+  // MplsSettings::asset_identity() of the settings each version directory was downloaded with.
+  pub(crate) asset_identities: HashMap<String, String>,
+  // fnv1a() of the binary in each version directory, filled in when dedupe_installs is on.
+  pub(crate) checksums: HashMap<String, String>,
+  // The verified SHA-256 of the asset each version directory was installed from, to notice a release
+  // that was re-published under the same tag.
+  pub(crate) asset_digests: HashMap<String, String>,
+  // The newest downloaded version that passed its --version check, to roll back to.
+  pub(crate) last_good_version: Option<String>,
+  // Version directories whose binary failed that check. Kept on disk for debugging, but never picked.
+  pub(crate) failed_installs: Vec<String>,
+  // The latest release seen on each channel, for /mpls-doctor, which doesn't go online itself.
+  pub(crate) latest_versions: HashMap<String, String>,
+}
+
+impl Metadata {
+  // Never fails: metadata only ever saves work, so a missing, unreadable or corrupt file
+  // (e.g. from a write that was cut short) is treated as empty instead of blocking resolution.
+  pub(crate) fn load() -> Self {
+    match fs::read_to_string(METADATA_PATH) {
+      Ok(json) => Self::parse(&json).unwrap_or_else(|err| {
+        log!("{} is malformed, starting over: {}", METADATA_PATH, err);
+        let metadata = Self::default();
+        if let Err(err) = metadata.save() {
+          log!("{}", err);
+        }
+        metadata
+      }),
+      Err(err) if err.kind() == ErrorKind::NotFound => Self::default(),
+      Err(err) => {
+        log!("Can't read {}, ignoring it: {}", METADATA_PATH, err);
+        Self::default()
+      }
+    }
+  }
+
+  // Missing fields take their defaults and unknown ones are ignored, so that the file written by
+  // an older or newer version of the extension still loads.
+  fn parse(json: &str) -> serde_json::Result<Self> {
+    serde_json::from_str(json)
+  }
+
+  // Drops what's recorded about a version directory that was removed.
+  pub(crate) fn forget_install(&mut self, dir_name: &str) {
+    self.failed_installs.retain(|failed| failed != dir_name);
+    self.asset_identities.remove(dir_name);
+    self.checksums.remove(dir_name);
+    self.asset_digests.remove(dir_name);
+  }
+
+  pub(crate) fn save(&self) -> zed::Result<()> {
+    let json = serde_json::to_string_pretty(self).map_err(|err| err.to_string())?;
+    fs::write(METADATA_PATH, json).map_err(|err| format!("Can't write {}: {}", METADATA_PATH, err))
+  }
+}
+
+// FNV-1a, because std's hashers aren't guaranteed to be stable across Rust releases
+// and its results end up in mpls-metadata.json.
+pub(crate) fn fnv1a(bytes: &[u8]) -> String {
+  let hash = bytes.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+    (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+  });
+  format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn rejects_truncated_and_garbage_files() {
+    assert!(
+      Metadata::parse(r#"{"active_version": "mpls_0.16.0_linux_amd64", "asset_ide"#).is_err()
+    );
+    assert!(Metadata::parse("not json").is_err());
+    assert!(Metadata::parse("").is_err());
+  }
+
+  #[test]
+  fn tolerates_unknown_and_missing_fields() {
+    let metadata = Metadata::parse(
+      r#"{"active_version": "mpls_0.16.0_linux_amd64", "written_by": "a newer extension"}"#,
+    )
+    .unwrap();
+    assert_eq!(
+      metadata.active_version.as_deref(),
+      Some("mpls_0.16.0_linux_amd64")
+    );
+    assert!(metadata.asset_identities.is_empty());
+    assert!(metadata.failed_installs.is_empty());
+    assert_eq!(metadata.last_good_version, None);
+  }
+
+  #[test]
+  fn forgets_only_the_removed_install() {
+    let mut metadata = Metadata::default();
+    for dir_name in ["mpls_0.15.0_linux_amd64", "mpls_0.16.0_linux_amd64"] {
+      metadata.failed_installs.push(dir_name.to_string());
+      for records in [
+        &mut metadata.asset_identities,
+        &mut metadata.checksums,
+        &mut metadata.asset_digests,
+      ] {
+        records.insert(dir_name.to_string(), "recorded".to_string());
+      }
+    }
+    metadata.forget_install("mpls_0.15.0_linux_amd64");
+    assert_eq!(metadata.failed_installs, ["mpls_0.16.0_linux_amd64"]);
+    for records in [
+      &metadata.asset_identities,
+      &metadata.checksums,
+      &metadata.asset_digests,
+    ] {
+      assert_eq!(
+        records.keys().collect::<Vec<_>>(),
+        ["mpls_0.16.0_linux_amd64"]
+      );
+    }
+  }
+}
@@ -0,0 +1,278 @@
+use crate::{MplsSettings, platform, settings::InstallLayout, version::VERSION_PATTERN};
+use regex::Regex;
+use zed_extension_api as zed;
+
+// mpls_0.16.0_linux_amd64.tar.gz extracts into mpls_0.16.0_linux_amd64/
+pub(crate) const DEFAULT_ASSET_NAME_TEMPLATE: &str = "mpls_{version}_{os}_{arch}.{ext}";
+
+// The version directory for install_layout "versioned", whatever the asset is called.
+const VERSIONED_DIR_TEMPLATE: &str = "mpls-{version}";
+
+// Placeholders that have to appear in a template so that it names exactly one asset.
+const REQUIRED_PLACEHOLDERS: [&str; 3] = ["{version}", "{os}", "{arch}"];
+
+// How release assets and their extracted version directories are named on this platform.
+pub(crate) struct AssetNaming {
+  template: String,
+  binary_name: String,
+  executable_subpath: Option<String>,
+  layout: InstallLayout,
+  preferred_archive: Option<Vec<String>>,
+  pub(crate) os: &'static str,
+  pub(crate) arch: &'static str,
+}
+
+impl AssetNaming {
+  pub(crate) fn for_platform(settings: &MplsSettings) -> zed::Result<Self> {
+    let (os, arch) = platform()?;
+    Ok(Self::new(settings, os, arch))
+  }
+
+  // For a platform given by its asset names, e.g. "linux" and "amd64".
+  pub(crate) fn new(settings: &MplsSettings, os: &'static str, arch: &'static str) -> Self {
+    Self {
+      template: settings
+        .asset_name_template
+        .clone()
+        .unwrap_or_else(|| DEFAULT_ASSET_NAME_TEMPLATE.to_string()),
+      binary_name: settings.binary_name.clone(),
+      executable_subpath: settings.executable_subpath.clone(),
+      layout: settings.install_layout,
+      preferred_archive: settings.preferred_archive.clone(),
+      os,
+      arch,
+    }
+  }
+
+  // How mpls archives its releases on this platform.
+  pub(crate) fn file_type(&self) -> zed::DownloadedFileType {
+    file_type_for(self.os)
+  }
+
+  pub(crate) fn ext(&self) -> &'static str {
+    file_ext(self.file_type())
+  }
+
+  // The name of the binary inside an extracted archive, e.g. mpls or mpls.exe
+  pub(crate) fn executable_name(&self) -> String {
+    if self.os == "windows" && !self.binary_name.ends_with(".exe") {
+      format!("{}.exe", self.binary_name)
+    } else {
+      self.binary_name.clone()
+    }
+  }
+
+  // The archive formats to look for, most preferred first, with how to extract each.
+  pub(crate) fn archive_formats(&self) -> Vec<(&'static str, zed::DownloadedFileType)> {
+    match &self.preferred_archive {
+      Some(preferred_archive) => preferred_archive
+        .iter()
+        .filter_map(|ext| archive_format(ext))
+        .collect(),
+      None => vec![(self.ext(), self.file_type())],
+    }
+  }
+
+  // Where the binary lives inside a version directory, e.g. mpls or bin/mpls
+  pub(crate) fn executable_subpath(&self) -> String {
+    self
+      .executable_subpath
+      .clone()
+      .unwrap_or_else(|| self.executable_name())
+  }
+
+  // The binary inside the given version directory, relative to the work directory.
+  pub(crate) fn executable_path(&self, dir_name: &str) -> String {
+    format!("{}/{}", dir_name, self.executable_subpath())
+  }
+
+  // The name of the release asset, e.g. mpls_0.16.0_linux_amd64.tar.gz
+  pub(crate) fn archived(&self, version: &str, ext: &str) -> String {
+    self.render(&self.template, version).replace("{ext}", ext)
+  }
+
+  // The name of the directory the asset is extracted into, e.g. mpls_0.16.0_linux_amd64,
+  // or mpls-0.16.0 with install_layout "versioned".
+  pub(crate) fn unarchived(&self, version: &str) -> String {
+    self.render(&self.unarchived_template(self.layout), version)
+  }
+
+  // Matches unarchived() names, capturing the version.
+  pub(crate) fn unarchived_regex(&self) -> Regex {
+    self.unarchived_regex_for(
+      &self.unarchived_template(self.layout),
+      &regex::escape(self.os),
+      &regex::escape(self.arch),
+    )
+  }
+
+  // What the offline scan matches: unarchived_regex() first, then the other install_layout,
+  // so that switching layouts doesn't hide versions installed before.
+  pub(crate) fn scan_regexes(&self) -> [Regex; 2] {
+    let other_layout = match self.layout {
+      InstallLayout::Asset => InstallLayout::Versioned,
+      InstallLayout::Versioned => InstallLayout::Asset,
+    };
+    [
+      self.unarchived_regex(),
+      self.unarchived_regex_for(
+        &self.unarchived_template(other_layout),
+        &regex::escape(self.os),
+        &regex::escape(self.arch),
+      ),
+    ]
+  }
+
+  // Like unarchived_regex(), but for any platform. Used to spot installs that can't run here.
+  // Only the asset layout names a platform.
+  pub(crate) fn any_platform_regex(&self) -> Regex {
+    self.unarchived_regex_for(
+      &self.unarchived_template(InstallLayout::Asset),
+      "[a-z0-9]+",
+      "[a-z0-9]+",
+    )
+  }
+
+  fn unarchived_regex_for(&self, template: &str, os_pattern: &str, arch_pattern: &str) -> Regex {
+    let placeholder_regex = Regex::new(r"\{(version|os|arch)\}").unwrap();
+
+    let mut pattern = String::from("^");
+    let mut version_captured = false;
+    let mut last_end = 0;
+    for placeholder in placeholder_regex.find_iter(template) {
+      pattern.push_str(&regex::escape(&template[last_end..placeholder.start()]));
+      match placeholder.as_str() {
+        // Only the first {version} captures. Later ones just have to look like a version.
+        "{version}" if !version_captured => {
+          pattern.push_str(&format!("({})", VERSION_PATTERN));
+          version_captured = true;
+        }
+        "{version}" => pattern.push_str(&format!("(?:{})", VERSION_PATTERN)),
+        "{os}" => pattern.push_str(os_pattern),
+        _ => pattern.push_str(arch_pattern),
+      }
+      last_end = placeholder.end();
+    }
+    pattern.push_str(&regex::escape(&template[last_end..]));
+    pattern.push('$');
+
+    // Everything but the placeholders is escaped, so this is a valid regex as long as the patterns are.
+    Regex::new(&pattern).unwrap()
+  }
+
+  fn unarchived_template(&self, layout: InstallLayout) -> String {
+    match layout {
+      InstallLayout::Asset => self.template.replacen(".{ext}", "", 1).replace("{ext}", ""),
+      InstallLayout::Versioned => VERSIONED_DIR_TEMPLATE.to_string(),
+    }
+  }
+
+  fn render(&self, template: &str, version: &str) -> String {
+    template
+      .replace("{version}", version)
+      .replace("{os}", self.os)
+      .replace("{arch}", self.arch)
+  }
+}
+
+// How mpls archives its releases for `os`, one of the names SUPPORTED_PLATFORMS uses.
+pub(crate) fn file_type_for(os: &str) -> zed::DownloadedFileType {
+  match os {
+    "windows" => zed::DownloadedFileType::Zip,
+    "linux" | "darwin" => zed::DownloadedFileType::GzipTar,
+    _ => unreachable!("There's a bug in the codebase"),
+  }
+}
+
+// The {ext} an asset of this type has.
+pub(crate) fn file_ext(file_type: zed::DownloadedFileType) -> &'static str {
+  match file_type {
+    zed::DownloadedFileType::Zip => "zip",
+    zed::DownloadedFileType::GzipTar => "tar.gz",
+    zed::DownloadedFileType::Gzip => "gz",
+    zed::DownloadedFileType::Uncompressed => "",
+  }
+}
+
+// The formats preferred_archive accepts. Both extract into a directory.
+pub(crate) fn archive_format(ext: &str) -> Option<(&'static str, zed::DownloadedFileType)> {
+  match ext {
+    "tar.gz" => Some(("tar.gz", zed::DownloadedFileType::GzipTar)),
+    "zip" => Some(("zip", zed::DownloadedFileType::Zip)),
+    _ => None,
+  }
+}
+
+pub(crate) fn validate_template(template: &str) -> zed::Result<()> {
+  let missing: Vec<&str> = REQUIRED_PLACEHOLDERS
+    .into_iter()
+    .filter(|placeholder| !template.contains(placeholder))
+    .collect();
+  if !missing.is_empty() {
+    return Err(format!(
+      "asset_name_template \"{}\" is missing {}. Without them it can't tell release assets apart.",
+      template,
+      missing.join(", ")
+    ));
+  }
+  if template.contains('/') || template.contains('\\') {
+    return Err(format!(
+      "asset_name_template \"{}\" must be a file name, not a path.",
+      template
+    ));
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use zed::DownloadedFileType;
+
+  #[test]
+  fn file_type_follows_the_os() {
+    assert!(matches!(file_type_for("windows"), DownloadedFileType::Zip));
+    assert!(matches!(
+      file_type_for("linux"),
+      DownloadedFileType::GzipTar
+    ));
+    assert!(matches!(
+      file_type_for("darwin"),
+      DownloadedFileType::GzipTar
+    ));
+  }
+
+  #[test]
+  fn file_ext_names_each_file_type() {
+    assert_eq!(file_ext(DownloadedFileType::Zip), "zip");
+    assert_eq!(file_ext(DownloadedFileType::GzipTar), "tar.gz");
+    assert_eq!(file_ext(DownloadedFileType::Gzip), "gz");
+    assert_eq!(file_ext(DownloadedFileType::Uncompressed), "");
+  }
+
+  #[test]
+  fn file_ext_round_trips_through_archive_format() {
+    for os in ["windows", "linux", "darwin"] {
+      let ext = file_ext(file_type_for(os));
+      assert_eq!(archive_format(ext).map(|(ext, _)| ext), Some(ext));
+    }
+  }
+
+  #[test]
+  fn windows_installs_mpls_exe_from_a_zip() {
+    let naming = AssetNaming::new(&MplsSettings::default(), "windows", "amd64");
+    assert!(matches!(naming.file_type(), DownloadedFileType::Zip));
+    assert_eq!(
+      naming.archived("0.16.0", naming.ext()),
+      "mpls_0.16.0_windows_amd64.zip"
+    );
+    assert_eq!(
+      naming.executable_path(&naming.unarchived("0.16.0")),
+      "mpls_0.16.0_windows_amd64/mpls.exe"
+    );
+    assert!(matches!(
+      naming.archive_formats()[..],
+      [("zip", DownloadedFileType::Zip)]
+    ));
+  }
+}
@@ -0,0 +1,97 @@
+use crate::{MPLS_REPO, installs, naming::AssetNaming};
+use std::{env::current_dir, fmt::Write};
+
+// Why we had to settle for an already-installed version.
+pub(crate) enum OfflineCause<'a> {
+  OfflineOnly,
+  InstalledLatest,
+  ReadOnlyWorkDir,
+  RateLimited(&'a str),
+  NetworkFailed(&'a str),
+  // The release was found, but installing it failed.
+  InstallFailed(&'a str),
+}
+
+// The error for when there is nothing installed we could fall back to.
+// This is what most new offline users hit first, so it spells out how to install MPLS by hand.
+pub(crate) fn no_installation_error(
+  naming: &AssetNaming,
+  pinned_version: Option<&str>,
+  cause: OfflineCause,
+) -> String {
+  let mut message = match pinned_version {
+    Some(version) => format!(
+      "MPLS {} is not installed for {}/{}",
+      version, naming.os, naming.arch
+    ),
+    None => format!(
+      "No installation of MPLS was found for {}/{}",
+      naming.os, naming.arch
+    ),
+  };
+  // Writing into a String never fails, so the results of write! are ignored.
+  let _ = match cause {
+    OfflineCause::OfflineOnly => write!(
+      message,
+      ", and offline_only is set so it can't be downloaded."
+    ),
+    OfflineCause::InstalledLatest => write!(
+      message,
+      ", and version is set to \"installed-latest\" so none will be downloaded."
+    ),
+    OfflineCause::ReadOnlyWorkDir => write!(
+      message,
+      ", and it can't be downloaded because {} is read-only. Install mpls on your PATH or set lsp.mpls.binary.path instead.",
+      work_dir()
+    ),
+    OfflineCause::RateLimited(err) => write!(
+      message,
+      ", and GitHub is rate-limiting release lookups so it can't be downloaded. Try again later. ({})",
+      err
+    ),
+    OfflineCause::NetworkFailed(err) => write!(
+      message,
+      ", and it can't be downloaded because looking up the release failed. Check your internet connection. ({})",
+      err
+    ),
+    OfflineCause::InstallFailed(err) => write!(
+      message,
+      " to fall back to, and installing the release failed: {}",
+      err
+    ),
+  };
+
+  let other_platforms = installs::other_platform_dir_names(naming);
+  if !other_platforms.is_empty() {
+    let _ = write!(
+      message,
+      " Found installations for other platforms, which can't run here: {}.",
+      other_platforms.join(", ")
+    );
+  }
+
+  let _ = write!(
+    message,
+    " {}",
+    manual_install_hint(naming, pinned_version.unwrap_or("<version>"))
+  );
+  message
+}
+
+// Where a hand-installed binary has to go for the extension to pick it up.
+pub(crate) fn manual_install_hint(naming: &AssetNaming, version: &str) -> String {
+  format!(
+    "To install it manually, download {} from https://github.com/{}/releases (or build a compatible binary) and extract it so that the binary ends up at {}/{}/{}",
+    naming.archived(version, naming.ext()),
+    MPLS_REPO,
+    work_dir(),
+    naming.unarchived(version),
+    naming.executable_subpath()
+  )
+}
+
+fn work_dir() -> String {
+  current_dir()
+    .map(|dir| dir.display().to_string())
+    .unwrap_or_else(|_| "the extension's work directory".to_string())
+}
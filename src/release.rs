@@ -0,0 +1,185 @@
+use crate::version::{self, Version};
+use zed_extension_api::{
+  http_client::{HttpMethod, HttpRequest, HttpResponseStream, RedirectPolicy},
+  serde_json,
+};
+
+// Why a GitHub release lookup failed, as far as we can tell from the error message Zed gives us.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LookupFailure {
+  // The release (usually a pinned tag) doesn't exist.
+  NotFound,
+  // GitHub refused to answer because we called its API too often.
+  RateLimited,
+  // Anything else, most likely no internet connection.
+  Offline,
+}
+
+impl LookupFailure {
+  // Zed reports HTTP errors as e.g. "status error 404, response: ...".
+  pub(crate) fn classify(err: &str) -> Self {
+    let err = err.to_ascii_lowercase();
+    if err.contains("404") || err.contains("not found") {
+      LookupFailure::NotFound
+    } else if err.contains("rate limit") || err.contains("403") || err.contains("429") {
+      LookupFailure::RateLimited
+    } else {
+      LookupFailure::Offline
+    }
+  }
+}
+
+// Where release lookups go.
+const GITHUB_API_URL: &str = "https://api.github.com";
+
+// Identifies the extension to GitHub and to proxies that reject anonymous clients.
+const DEFAULT_USER_AGENT: &str = concat!("zed-mpls/", env!("CARGO_PKG_VERSION"));
+
+// A HEAD request to GitHub's API, which is much cheaper to fail than a full release lookup.
+// Any HTTP response, even an error status, means the host is reachable.
+pub(crate) fn probe_github(user_agent: Option<&str>) -> Result<(), String> {
+  if let Some(user_agent) = user_agent {
+    log!("Probing GitHub with User-Agent {}", user_agent);
+  }
+  let request = HttpRequest::builder()
+    .method(HttpMethod::Head)
+    .url(GITHUB_API_URL)
+    .header("User-Agent", user_agent.unwrap_or(DEFAULT_USER_AGENT))
+    .redirect_policy(RedirectPolicy::NoFollow)
+    .build()?;
+  match request.fetch() {
+    Ok(_) => Ok(()),
+    Err(err) if err.to_ascii_lowercase().contains("status error") => Ok(()),
+    Err(err) => Err(format!("{} is unreachable: {}", GITHUB_API_URL, err)),
+  }
+}
+
+// A GET of GitHub's REST API, e.g. "repos/mhersson/mpls/tags", for what Zed's release lookups leave out.
+pub(crate) fn github_get(
+  path: &str,
+  user_agent: Option<&str>,
+) -> Result<serde_json::Value, String> {
+  let url = format!("{}/{}", GITHUB_API_URL, path);
+  let response = HttpRequest::builder()
+    .method(HttpMethod::Get)
+    .url(&url)
+    .header("User-Agent", user_agent.unwrap_or(DEFAULT_USER_AGENT))
+    .header("Accept", "application/vnd.github+json")
+    .redirect_policy(RedirectPolicy::FollowAll)
+    .build()?
+    .fetch()
+    .map_err(|err| format!("GET {} failed: {}", url, err))?;
+  serde_json::from_slice(&response.body).map_err(|err| format!("GET {} isn't JSON: {}", url, err))
+}
+
+// Streams the body of `url`, e.g. a release asset, following GitHub's redirect to where it's stored.
+pub(crate) fn fetch_stream(
+  url: &str,
+  user_agent: Option<&str>,
+) -> Result<HttpResponseStream, String> {
+  HttpRequest::builder()
+    .method(HttpMethod::Get)
+    .url(url)
+    .header("User-Agent", user_agent.unwrap_or(DEFAULT_USER_AGENT))
+    .redirect_policy(RedirectPolicy::FollowAll)
+    .build()?
+    .fetch_stream()
+    .map_err(|err| format!("GET {} failed: {}", url, err))
+}
+
+// The tag of the newest of `repo`'s last 100 releases with an asset whose SHA-256 is `digest`, so that
+// asset_sha256 on its own finds the release it belongs to.
+pub(crate) fn tag_with_digest(
+  repo: &str,
+  digest: &str,
+  user_agent: Option<&str>,
+) -> Result<Option<String>, String> {
+  let releases = github_get(&format!("repos/{}/releases?per_page=100", repo), user_agent)?;
+  Ok(tag_with_digest_in(&releases, digest))
+}
+
+fn tag_with_digest_in(releases: &serde_json::Value, digest: &str) -> Option<String> {
+  let digest = format!("sha256:{}", digest);
+  let has_digest = |release: &&serde_json::Value| {
+    release["assets"].as_array().is_some_and(|assets| {
+      assets.iter().any(|asset| {
+        asset["digest"]
+          .as_str()
+          .is_some_and(|asset_digest| asset_digest.eq_ignore_ascii_case(&digest))
+      })
+    })
+  };
+  releases.as_array()?.iter().find(has_digest)?["tag_name"]
+    .as_str()
+    .map(str::to_string)
+}
+
+// The newest of `repo`'s tags, if it's newer than `current`. latest_github_release with require_assets
+// skips a release whose binaries haven't been uploaded yet, but its tag is already there.
+pub(crate) fn newer_tag(
+  repo: &str,
+  current: &str,
+  pre_release: bool,
+  user_agent: Option<&str>,
+) -> Result<Option<String>, String> {
+  let tags = github_get(&format!("repos/{}/tags?per_page=100", repo), user_agent)?;
+  Ok(newer_tag_in(&tags, current, pre_release))
+}
+
+fn newer_tag_in(tags: &serde_json::Value, current: &str, pre_release: bool) -> Option<String> {
+  let parse = |tag: &str| Version::parse(version::asset_version(tag).ok()?).ok();
+  let current = parse(current)?;
+  tags
+    .as_array()?
+    .iter()
+    .filter_map(|tag| tag.get("name")?.as_str())
+    .filter_map(|name| Some((parse(name)?, name)))
+    .filter(|(version, _)| pre_release || version.pre.is_empty())
+    .filter(|(version, _)| *version > current)
+    .max_by(|(a, _), (b, _)| a.cmp(b))
+    .map(|(_, name)| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn finds_a_newer_tag() {
+    let tags = serde_json::json!([
+      { "name": "v0.17.0-rc.1" },
+      { "name": "nightly" },
+      { "name": "v0.15.0" },
+      { "name": "v0.16.0" },
+    ]);
+    assert_eq!(
+      newer_tag_in(&tags, "v0.15.0", false).as_deref(),
+      Some("v0.16.0")
+    );
+    assert_eq!(newer_tag_in(&tags, "v0.16.0", false), None);
+    assert_eq!(
+      newer_tag_in(&tags, "v0.16.0", true).as_deref(),
+      Some("v0.17.0-rc.1")
+    );
+    assert_eq!(newer_tag_in(&serde_json::json!({}), "v0.15.0", false), None);
+  }
+
+  #[test]
+  fn finds_the_release_with_a_digest() {
+    let digest = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    let releases = serde_json::json!([
+      { "tag_name": "v0.17.0", "assets": [{ "name": "mpls_0.17.0_linux_amd64.tar.gz", "digest": null }] },
+      { "tag_name": "v0.16.0", "assets": [
+        { "name": "mpls_0.16.0_checksums.txt", "digest": "sha256:0000000000000000000000000000000000000000000000000000000000000000" },
+        { "name": "mpls_0.16.0_linux_amd64.tar.gz", "digest": format!("sha256:{}", digest.to_ascii_uppercase()) },
+      ] },
+      { "tag_name": "v0.15.0", "assets": [] },
+    ]);
+    assert_eq!(
+      tag_with_digest_in(&releases, digest).as_deref(),
+      Some("v0.16.0")
+    );
+    assert_eq!(tag_with_digest_in(&releases, &"f".repeat(64)), None);
+    assert_eq!(tag_with_digest_in(&serde_json::json!({}), digest), None);
+  }
+}
@@ -0,0 +1,23 @@
+use std::{thread, time::Duration};
+use zed_extension_api as zed;
+
+// Runs `f` until it succeeds, at most 1 + `retries` times, sleeping `interval_ms` between attempts.
+// The last error is returned if every attempt fails, or as soon as `is_retryable` rejects one.
+pub(crate) fn retry<T>(
+  retries: u32,
+  interval_ms: u64,
+  is_retryable: impl Fn(&str) -> bool,
+  mut f: impl FnMut(u32) -> zed::Result<T>,
+) -> zed::Result<T> {
+  let mut attempt = 0;
+  loop {
+    match f(attempt) {
+      Ok(value) => return Ok(value),
+      Err(err) if attempt >= retries || !is_retryable(&err) => return Err(err),
+      Err(_) => {
+        thread::sleep(Duration::from_millis(interval_ms));
+        attempt += 1;
+      }
+    }
+  }
+}
@@ -0,0 +1,843 @@
+use crate::{
+  metadata, naming,
+  version::{self, Version},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use zed_extension_api::{self as zed, serde_json, settings::LspSettings};
+
+// The contents of `lsp.mpls.settings` in Zed's settings.json.
+// Everything is resolved per worktree, so each project can override it in its own .zed/settings.json.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct MplsSettings {
+  pub(crate) enable_emoji: bool,
+  pub(crate) enable_wikilinks: bool,
+  pub(crate) enable_footnotes: bool,
+  // Installs this exact release instead of the latest one, e.g. "0.16.0".
+  pub(crate) version: Option<String>,
+  // Overrides how release assets are named, for forks. Supports {version}, {os}, {arch} and {ext}.
+  pub(crate) asset_name_template: Option<String>,
+  // Never touches the network; only PATH and already-installed versions are considered.
+  pub(crate) offline_only: bool,
+  // How often a failed download or offline scan is retried, and how long to wait in between.
+  pub(crate) download_retries: u32,
+  pub(crate) retry_interval_ms: u64,
+  pub(crate) scan_retries: u32,
+  // Passed to latest_github_release. Forks that attach assets after publishing may want false.
+  pub(crate) require_assets: bool,
+  // Program and arguments run after every download, with the binary's path appended.
+  // Comes from project settings just as well as from user settings; nothing tells the two apart.
+  pub(crate) post_install_command: Option<Vec<String>>,
+  pub(crate) channel: Channel,
+  // Looked up on PATH, and expected inside release archives (with .exe appended on Windows).
+  pub(crate) binary_name: String,
+  // A GitHub tag used verbatim, for tags that aren't clean semver (e.g. "v0.16.0-hotfix").
+  // Takes precedence over `version`.
+  pub(crate) release_tag: Option<String>,
+  // false ignores binary_name on PATH, so that only the managed install is ever used.
+  pub(crate) use_path_binary: bool,
+  // Enables exactly these mpls features, e.g. ["emoji", "footnotes"], instead of the enable_* booleans.
+  pub(crate) features: Option<Vec<String>>,
+  // Features that stay off whatever features, the enable_* booleans or a profile say.
+  pub(crate) disabled_features: Vec<String>,
+  // Checks that GitHub is reachable before looking up a release, to fall back offline sooner.
+  pub(crate) network_probe: bool,
+  // Hard-links a downloaded binary to an identical one that's already installed, e.g. after a re-tag.
+  pub(crate) dedupe_installs: bool,
+  // How many of a release's assets are looked at when picking ours.
+  pub(crate) asset_scan_limit: usize,
+  // Removes every other installed version after a successful download.
+  pub(crate) clean_install: bool,
+  // Where the binary lives inside an extracted archive, e.g. "bin/mpls". Defaults to binary_name.
+  pub(crate) executable_subpath: Option<String>,
+  pub(crate) install_layout: InstallLayout,
+  // Only assets ending in one of these are considered, e.g. to skip .sha256 and .sig files.
+  pub(crate) asset_extensions: Vec<String>,
+  // Archive formats in order of preference, for releases that publish several per platform.
+  pub(crate) preferred_archive: Option<Vec<String>>,
+  // Sent with the extension's own requests to GitHub. Zed's release lookups and downloads use its own.
+  pub(crate) user_agent: Option<String>,
+  // Runs every download with --version before using it, rolling back when that fails.
+  pub(crate) verify_binary: bool,
+  // Checks every download against the SHA-256 in the release's checksums file.
+  pub(crate) verify_checksums: bool,
+  // The SHA-256 the asset has to have, checked whatever verify_checksums says, for reproducible installs.
+  pub(crate) asset_sha256: Option<String>,
+  // Named bundles of settings. The one named by active_profile is the baseline the other keys override.
+  pub(crate) profiles: BTreeMap<String, serde_json::Map<String, serde_json::Value>>,
+  pub(crate) active_profile: Option<String>,
+  pub(crate) platform_fallback: PlatformFallback,
+  // lsp.mpls.binary.arguments, which replaces the flags built from the settings above.
+  #[serde(skip)]
+  pub(crate) binary_arguments: Option<Vec<String>>,
+  // lsp.mpls.binary.path, launched instead of anything found or installed.
+  #[serde(skip)]
+  pub(crate) binary_path: Option<String>,
+}
+
+// How version directories are named.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum InstallLayout {
+  // After the asset, e.g. mpls_0.16.0_linux_amd64
+  #[default]
+  Asset,
+  // mpls-0.16.0, for scripts that want a predictable path.
+  Versioned,
+}
+
+// What to do on a platform MPLS doesn't publish builds for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum PlatformFallback {
+  // Fail with the list of supported platforms.
+  #[default]
+  Error,
+  // Use binary_name from PATH, even with use_path_binary false or a version it doesn't match.
+  Path,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Channel {
+  // Tagged releases only.
+  #[default]
+  Stable,
+  // The most recent pre-release, e.g. a rolling nightly build or a release candidate.
+  #[serde(alias = "pre-release")]
+  Nightly,
+}
+
+impl Channel {
+  pub(crate) fn as_str(self) -> &'static str {
+    match self {
+      Channel::Stable => "stable",
+      Channel::Nightly => "nightly",
+    }
+  }
+
+  // Whether an installed version may be picked when this channel resolves offline.
+  pub(crate) fn accepts(self, version: &Version) -> bool {
+    self == Channel::Nightly || version.pre.is_empty()
+  }
+}
+
+// The names `features` accepts, each enabling --enable-<name>.
+const FEATURES: [&str; 3] = ["emoji", "wikilinks", "footnotes"];
+
+// What /mpls-validate found.
+#[derive(Default)]
+pub(crate) struct SettingsReport {
+  pub(crate) errors: Vec<String>,
+  pub(crate) warnings: Vec<String>,
+}
+
+// The `version` that means "whatever is newest locally".
+pub(crate) const INSTALLED_LATEST: &str = "installed-latest";
+
+const MAX_RETRIES: u32 = 10;
+const MAX_RETRY_INTERVAL_MS: u64 = 60_000;
+
+impl Default for MplsSettings {
+  fn default() -> Self {
+    Self {
+      enable_emoji: true,
+      enable_wikilinks: true,
+      enable_footnotes: true,
+      version: None,
+      asset_name_template: None,
+      offline_only: false,
+      download_retries: 2,
+      retry_interval_ms: 1000,
+      scan_retries: 1,
+      require_assets: true,
+      post_install_command: None,
+      channel: Channel::Stable,
+      binary_name: "mpls".to_string(),
+      release_tag: None,
+      use_path_binary: true,
+      features: None,
+      disabled_features: Vec::new(),
+      network_probe: true,
+      dedupe_installs: false,
+      asset_scan_limit: 100,
+      clean_install: false,
+      executable_subpath: None,
+      install_layout: InstallLayout::Asset,
+      asset_extensions: vec![".tar.gz".to_string(), ".zip".to_string(), ".gz".to_string()],
+      preferred_archive: None,
+      user_agent: None,
+      verify_binary: true,
+      verify_checksums: true,
+      asset_sha256: None,
+      profiles: BTreeMap::new(),
+      active_profile: None,
+      platform_fallback: PlatformFallback::Error,
+      binary_arguments: None,
+      binary_path: None,
+    }
+  }
+}
+
+impl MplsSettings {
+  pub(crate) fn for_worktree(
+    language_server_name: &str,
+    worktree: &zed::Worktree,
+  ) -> zed::Result<Self> {
+    let lsp_settings = LspSettings::for_worktree(language_server_name, worktree)?;
+    let mut settings: Self = match lsp_settings.settings {
+      Some(settings) => serde_json::from_value(with_profile(settings)?)
+        .map_err(|err| format!("Invalid settings for {}: {}", language_server_name, err))?,
+      None => Self::default(),
+    };
+    if let Some(binary) = lsp_settings.binary {
+      settings.binary_arguments = binary.arguments;
+      settings.binary_path = binary.path;
+    }
+    settings.validate()?;
+    Ok(settings)
+  }
+
+  fn validate(&self) -> zed::Result<()> {
+    match self.problems().into_iter().next() {
+      Some(problem) => Err(problem),
+      None => Ok(()),
+    }
+  }
+
+  // Every reason these settings are unusable, in the order of the fields.
+  fn problems(&self) -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Some(template) = &self.asset_name_template
+      && let Err(err) = naming::validate_template(template)
+    {
+      problems.push(err);
+    }
+    if self.binary_name.is_empty()
+      || self.binary_name == "."
+      || self.binary_name == ".."
+      || self.binary_name.contains(['/', '\\'])
+    {
+      problems.push(format!(
+        "binary_name \"{}\" must be a plain file name like \"mpls\". Use a PATH entry for binaries elsewhere.",
+        self.binary_name
+      ));
+    }
+    if let Some(subpath) = &self.executable_subpath
+      && (subpath.is_empty()
+        || subpath.starts_with(['/', '\\'])
+        || subpath.contains(':')
+        || subpath
+          .split(['/', '\\'])
+          .any(|component| component == ".."))
+    {
+      problems.push(format!(
+        "executable_subpath \"{}\" must be a relative path inside the archive, like \"bin/mpls\".",
+        subpath
+      ));
+    }
+    for (name, retries) in [
+      ("download_retries", self.download_retries),
+      ("scan_retries", self.scan_retries),
+    ] {
+      if retries > MAX_RETRIES {
+        problems.push(format!(
+          "{} is {}, but it has to be between 0 and {}.",
+          name, retries, MAX_RETRIES
+        ));
+      }
+    }
+    if self
+      .post_install_command
+      .as_ref()
+      .is_some_and(|command| command.is_empty())
+    {
+      problems.push("post_install_command needs at least the program to run.".to_string());
+    }
+    for (setting, feature) in self
+      .features
+      .iter()
+      .flatten()
+      .map(|feature| ("features", feature))
+      .chain(
+        self
+          .disabled_features
+          .iter()
+          .map(|feature| ("disabled_features", feature)),
+      )
+    {
+      if !FEATURES.contains(&feature.as_str()) {
+        problems.push(match closest(feature, &FEATURES) {
+          Some(suggestion) => format!(
+            "Unknown feature \"{}\" in {}. Did you mean \"{}\"?",
+            feature, setting, suggestion
+          ),
+          None => format!(
+            "Unknown feature \"{}\" in {}. Known features are: {}.",
+            feature,
+            setting,
+            FEATURES.join(", ")
+          ),
+        });
+      }
+    }
+    if let Some(user_agent) = &self.user_agent
+      && (user_agent.trim().is_empty() || user_agent.contains(['\r', '\n']))
+    {
+      problems.push("user_agent has to be a non-empty, single line.".to_string());
+    }
+    match &self.preferred_archive {
+      Some(preferred_archive) if preferred_archive.is_empty() => {
+        problems.push("preferred_archive needs at least one format, like \"tar.gz\".".to_string());
+      }
+      Some(preferred_archive) => {
+        for ext in preferred_archive {
+          if naming::archive_format(ext).is_none() {
+            problems.push(format!(
+              "preferred_archive \"{}\" isn't supported. Use \"tar.gz\" or \"zip\".",
+              ext
+            ));
+          }
+        }
+      }
+      None => {}
+    }
+    if self.asset_extensions.is_empty() {
+      problems.push("asset_extensions needs at least one extension, like \".tar.gz\".".to_string());
+    }
+    if let Some(digest) = &self.asset_sha256
+      && (digest.len() != 64 || !digest.bytes().all(|byte| byte.is_ascii_hexdigit()))
+    {
+      problems.push(format!(
+        "asset_sha256 \"{}\" has to be a SHA-256 digest: 64 hexadecimal digits.",
+        digest
+      ));
+    }
+    if self.asset_scan_limit == 0 {
+      problems.push("asset_scan_limit has to be at least 1.".to_string());
+    }
+    if self.retry_interval_ms > MAX_RETRY_INTERVAL_MS {
+      problems.push(format!(
+        "retry_interval_ms is {}, but it has to be between 0 and {}.",
+        self.retry_interval_ms, MAX_RETRY_INTERVAL_MS
+      ));
+    }
+    // Zed spawns processes without a shell, but a NUL byte still can't go into an argument or a path.
+    for (key, value) in string_settings(self) {
+      if value.contains('\0') {
+        problems.push(format!("{} contains a null byte.", key));
+      }
+    }
+    problems
+  }
+
+  // String values that are accepted, but probably not what was meant.
+  fn string_warnings(&self) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for (key, value) in string_settings(self) {
+      if value.trim() != value {
+        warnings.push(format!(
+          "{} \"{}\" has leading or trailing whitespace, which is used as it is.",
+          key, value
+        ));
+      }
+      // These are meant to be flags, for the hook program and for mpls.
+      if value.starts_with('-') && key != "post_install_command" && key != "binary.arguments" {
+        warnings.push(format!(
+          "{} \"{}\" starts with a dash. It's a value, not a flag, and is never passed to mpls.",
+          key, value
+        ));
+      }
+    }
+    warnings
+  }
+
+  // Like for_worktree, but reports every problem instead of stopping at the first, along with
+  // warnings about things that don't stop the extension but probably aren't what was meant.
+  pub(crate) fn check(
+    language_server_name: &str,
+    worktree: &zed::Worktree,
+  ) -> zed::Result<SettingsReport> {
+    let mut report = SettingsReport::default();
+    let lsp_settings = LspSettings::for_worktree(language_server_name, worktree)?;
+    // lsp.mpls.binary is checked too, even without any lsp.mpls.settings.
+    let settings = lsp_settings
+      .settings
+      .unwrap_or_else(|| serde_json::Value::Object(serde_json::Map::new()));
+    // The active profile's keys are checked along with the rest.
+    let settings = match with_profile(settings) {
+      Ok(settings) => settings,
+      Err(err) => {
+        report.errors.push(err);
+        return Ok(report);
+      }
+    };
+    let Some(object) = settings.as_object() else {
+      report.errors.push(format!(
+        "lsp.{}.settings has to be an object.",
+        language_server_name
+      ));
+      return Ok(report);
+    };
+
+    let known_keys = known_keys();
+    for (key, value) in object {
+      if !known_keys.contains(key) {
+        let known_keys: Vec<&str> = known_keys.iter().map(String::as_str).collect();
+        report.warnings.push(match closest(key, &known_keys) {
+          Some(suggestion) => format!(
+            "Unknown setting \"{}\" is ignored. Did you mean \"{}\"?",
+            key, suggestion
+          ),
+          None => format!("Unknown setting \"{}\" is ignored.", key),
+        });
+        continue;
+      }
+      // One field at a time, so that a bad value doesn't hide the ones after it.
+      let field = serde_json::Value::Object([(key.clone(), value.clone())].into_iter().collect());
+      if let Err(err) = serde_json::from_value::<Self>(field) {
+        report.errors.push(format!("{}: {}", key, err));
+      }
+    }
+    if !report.errors.is_empty() {
+      return Ok(report);
+    }
+
+    let mut settings: Self = serde_json::from_value(settings).map_err(|err| err.to_string())?;
+    if let Some(binary) = lsp_settings.binary {
+      settings.binary_arguments = binary.arguments;
+      settings.binary_path = binary.path;
+    }
+    report.errors.extend(settings.problems());
+    report.warnings.extend(settings.string_warnings());
+    if let Some((program, _)) = settings
+      .post_install_command
+      .as_ref()
+      .and_then(|command| command.split_first())
+      && !program.contains(['/', '\\'])
+      && worktree.which(program).is_none()
+    {
+      report.warnings.push(format!(
+        "post_install_command {} isn't on your PATH, so every download would fail.",
+        program
+      ));
+    }
+    Ok(report)
+  }
+
+  // The settings that differ from the defaults, as they'd be written in lsp.mpls.settings.
+  pub(crate) fn non_default_json(&self) -> zed::Result<serde_json::Value> {
+    let to_object = |settings: &Self| match serde_json::to_value(settings) {
+      Ok(serde_json::Value::Object(object)) => Ok(object),
+      Ok(_) => Err("Settings didn't serialize to an object".to_string()),
+      Err(err) => Err(err.to_string()),
+    };
+    let defaults = to_object(&Self::default())?;
+    let object = to_object(self)?
+      .into_iter()
+      .filter(|(key, value)| defaults.get(key) != Some(value))
+      .collect();
+    Ok(serde_json::Value::Object(object))
+  }
+
+  // The `binary` object of lsp.mpls, which lives next to `settings` rather than in it.
+  pub(crate) fn binary_json(&self) -> Option<serde_json::Value> {
+    let mut object = serde_json::Map::new();
+    if let Some(arguments) = &self.binary_arguments {
+      object.insert("arguments".to_string(), serde_json::json!(arguments));
+    }
+    if let Some(path) = &self.binary_path {
+      object.insert("path".to_string(), serde_json::json!(path));
+    }
+    (!object.is_empty()).then_some(serde_json::Value::Object(object))
+  }
+
+  // A fingerprint of the settings that decide which asset gets installed. When it changes,
+  // a cached or previously downloaded binary may no longer be the one the user asked for.
+  pub(crate) fn asset_identity(&self) -> String {
+    let mut identity = format!(
+      "channel={};template={};binary_name={}",
+      self.channel.as_str(),
+      self.asset_name_template.as_deref().unwrap_or(""),
+      self.binary_name
+    );
+    // Only when they aren't the default, so that installs recorded before these settings existed
+    // keep their identity.
+    if let Some(subpath) = &self.executable_subpath {
+      identity.push_str(&format!(";executable_subpath={}", subpath));
+    }
+    if self.install_layout == InstallLayout::Versioned {
+      identity.push_str(";install_layout=versioned");
+    }
+    metadata::fnv1a(identity.as_bytes())
+  }
+
+  // What a path resolved this session is cached under: asset_identity() plus every setting that
+  // changes which step of the resolution order picks the binary, so that changing any of them
+  // takes effect on the next restart of the language server.
+  pub(crate) fn resolution_key(&self) -> String {
+    let key = format!(
+      "asset_identity={};pinned_tag={};installed_latest={};use_path_binary={};offline_only={};platform_fallback={:?};binary_path={};asset_sha256={}",
+      self.asset_identity(),
+      self.pinned_tag().unwrap_or_default(),
+      self.wants_installed_latest(),
+      self.use_path_binary,
+      self.offline_only,
+      self.platform_fallback,
+      self.binary_path.as_deref().unwrap_or(""),
+      self.asset_sha256.as_deref().unwrap_or("")
+    );
+    metadata::fnv1a(key.as_bytes())
+  }
+
+  pub(crate) fn release_options(&self) -> zed::GithubReleaseOptions {
+    zed::GithubReleaseOptions {
+      require_assets: self.require_assets,
+      pre_release: self.channel == Channel::Nightly,
+    }
+  }
+
+  // The pinned version as it appears in asset names, i.e. without the tag's "v" prefix.
+  pub(crate) fn pinned_version(&self) -> Option<&str> {
+    match &self.release_tag {
+      // A tag without a version in it, e.g. "nightly", goes into asset names as it is.
+      Some(tag) => Some(version::asset_version(tag).unwrap_or(tag)),
+      None => self
+        .version
+        .as_deref()
+        .filter(|version| *version != INSTALLED_LATEST)
+        .map(|version| version.trim_start_matches('v')),
+    }
+  }
+
+  // `"version": "installed-latest"`: the newest installed version, without ever downloading one.
+  pub(crate) fn wants_installed_latest(&self) -> bool {
+    self.release_tag.is_none() && self.version.as_deref() == Some(INSTALLED_LATEST)
+  }
+
+  // The tag to look the pinned release up by.
+  pub(crate) fn pinned_tag(&self) -> Option<String> {
+    match &self.release_tag {
+      Some(tag) => Some(tag.clone()),
+      None => self.pinned_version().map(|version| format!("v{}", version)),
+    }
+  }
+
+  // The setting the pin comes from, for error messages.
+  pub(crate) fn pin_setting_name(&self) -> &'static str {
+    if self.release_tag.is_some() {
+      "release_tag"
+    } else {
+      "version"
+    }
+  }
+
+  pub(crate) fn arguments(&self) -> Vec<String> {
+    // Taken as they are, except that disabled_features still wins.
+    if let Some(binary_arguments) = &self.binary_arguments {
+      return binary_arguments
+        .iter()
+        .filter(|argument| {
+          !self
+            .disabled_features
+            .iter()
+            .any(|feature| **argument == format!("--enable-{}", feature))
+        })
+        .cloned()
+        .collect();
+    }
+    let mut arguments = Vec::new();
+    let enabled = [
+      self.enable_emoji,
+      self.enable_wikilinks,
+      self.enable_footnotes,
+    ];
+    for (feature, enabled) in FEATURES.iter().zip(enabled) {
+      // A features list replaces the booleans entirely.
+      let enabled = match &self.features {
+        Some(features) => features.iter().any(|name| name == feature),
+        None => enabled,
+      };
+      if enabled && !self.disabled_features.iter().any(|name| name == feature) {
+        arguments.push(format!("--enable-{}", feature));
+      }
+    }
+    arguments
+  }
+}
+
+// Lays the settings over the profile named by their active_profile, so that everything set
+// explicitly wins. Settings without an active_profile are returned as they are.
+fn with_profile(settings: serde_json::Value) -> zed::Result<serde_json::Value> {
+  let serde_json::Value::Object(object) = settings else {
+    return Ok(settings);
+  };
+  let Some(active_profile) = object.get("active_profile").and_then(|name| name.as_str()) else {
+    return Ok(serde_json::Value::Object(object));
+  };
+  let profiles = object
+    .get("profiles")
+    .and_then(|profiles| profiles.as_object());
+  let Some(profile) = profiles.and_then(|profiles| profiles.get(active_profile)) else {
+    let names: Vec<&str> = profiles
+      .into_iter()
+      .flat_map(|profiles| profiles.keys())
+      .map(String::as_str)
+      .collect();
+    return Err(if names.is_empty() {
+      format!(
+        "active_profile is \"{}\", but there are no profiles.",
+        active_profile
+      )
+    } else {
+      format!(
+        "active_profile \"{}\" isn't one of the profiles: {}.",
+        active_profile,
+        names.join(", ")
+      )
+    });
+  };
+  let Some(profile) = profile.as_object() else {
+    return Err(format!(
+      "Profile \"{}\" has to be an object.",
+      active_profile
+    ));
+  };
+  if profile.contains_key("profiles") || profile.contains_key("active_profile") {
+    return Err(format!(
+      "Profile \"{}\" can't set profiles or active_profile.",
+      active_profile
+    ));
+  }
+  let mut merged = profile.clone();
+  merged.extend(object);
+  Ok(serde_json::Value::Object(merged))
+}
+
+// The candidate closest to a misspelt name, if any is close enough to be a plausible typo.
+fn closest<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+  let name = name.to_ascii_lowercase();
+  candidates
+    .iter()
+    .map(|candidate| (edit_distance(&name, candidate), *candidate))
+    .filter(|(distance, _)| *distance <= 2)
+    .min_by_key(|(distance, _)| *distance)
+    .map(|(_, candidate)| candidate)
+}
+
+// Every key lsp.mpls.settings understands, taken from the struct itself so it can't go stale.
+fn known_keys() -> Vec<String> {
+  match serde_json::to_value(MplsSettings::default()) {
+    Ok(serde_json::Value::Object(object)) => object.keys().cloned().collect(),
+    _ => Vec::new(),
+  }
+}
+
+// Every string in the settings, with the key it's under, e.g. each entry of post_install_command.
+fn string_settings(settings: &MplsSettings) -> Vec<(String, String)> {
+  fn collect(key: &str, value: &serde_json::Value, strings: &mut Vec<(String, String)>) {
+    match value {
+      serde_json::Value::String(value) => strings.push((key.to_string(), value.clone())),
+      serde_json::Value::Array(values) => {
+        for value in values {
+          collect(key, value, strings);
+        }
+      }
+      _ => {}
+    }
+  }
+  let mut strings = Vec::new();
+  if let Ok(serde_json::Value::Object(object)) = serde_json::to_value(settings) {
+    for (key, value) in &object {
+      collect(key, value, &mut strings);
+    }
+  }
+  // Skipped when serializing, since they come from lsp.mpls.binary rather than lsp.mpls.settings.
+  for argument in settings.binary_arguments.iter().flatten() {
+    strings.push(("binary.arguments".to_string(), argument.clone()));
+  }
+  if let Some(path) = &settings.binary_path {
+    strings.push(("binary.path".to_string(), path.clone()));
+  }
+  strings
+}
+
+// Levenshtein distance, one row at a time.
+fn edit_distance(a: &str, b: &str) -> usize {
+  let b: Vec<char> = b.chars().collect();
+  let mut row: Vec<usize> = (0..=b.len()).collect();
+  for (i, a) in a.chars().enumerate() {
+    let mut previous = row[0];
+    row[0] = i + 1;
+    for (j, b) in b.iter().enumerate() {
+      let substitution = previous + usize::from(a != *b);
+      previous = row[j + 1];
+      row[j + 1] = substitution.min(row[j] + 1).min(previous + 1);
+    }
+  }
+  row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn default_arguments_enable_every_feature() {
+    assert_eq!(
+      MplsSettings::default().arguments(),
+      ["--enable-emoji", "--enable-wikilinks", "--enable-footnotes"]
+    );
+  }
+
+  fn from_json(json: serde_json::Value) -> MplsSettings {
+    serde_json::from_value(json).unwrap()
+  }
+
+  #[test]
+  fn rejects_null_bytes() {
+    let settings = from_json(serde_json::json!({
+      "binary_name": "mpls\0",
+      "post_install_command": ["scan", "--file=\0"],
+    }));
+    let problems = settings.problems();
+    assert!(problems.contains(&"binary_name contains a null byte.".to_string()));
+    assert!(problems.contains(&"post_install_command contains a null byte.".to_string()));
+    assert!(MplsSettings::default().problems().is_empty());
+
+    let mut settings = MplsSettings {
+      binary_arguments: Some(vec!["--port".to_string(), "37418\0".to_string()]),
+      ..MplsSettings::default()
+    };
+    assert_eq!(
+      settings.problems(),
+      ["binary.arguments contains a null byte."]
+    );
+    settings.binary_arguments = None;
+    settings.binary_path = Some("bin/mpls\0".to_string());
+    assert_eq!(settings.problems(), ["binary.path contains a null byte."]);
+    // Flags are what binary.arguments is for.
+    settings.binary_path = None;
+    settings.binary_arguments = Some(vec!["--dark-mode".to_string()]);
+    assert!(settings.string_warnings().is_empty());
+  }
+
+  #[test]
+  fn warns_about_dashes_and_whitespace() {
+    let settings = from_json(serde_json::json!({
+      "version": "--enable-emoji",
+      "user_agent": " zed ",
+      "post_install_command": ["clamscan", "--no-summary"],
+    }));
+    let warnings = settings.string_warnings();
+    assert_eq!(warnings.len(), 2, "{:?}", warnings);
+    assert!(
+      warnings
+        .iter()
+        .any(|warning| warning.starts_with("version \"--enable-emoji\" starts with a dash"))
+    );
+    assert!(
+      warnings
+        .iter()
+        .any(|warning| warning.starts_with("user_agent \" zed \" has leading"))
+    );
+    assert!(MplsSettings::default().string_warnings().is_empty());
+  }
+
+  #[test]
+  fn path_binary_can_be_turned_off() {
+    assert!(MplsSettings::default().use_path_binary);
+    let settings = from_json(serde_json::json!({ "use_path_binary": false }));
+    assert!(!settings.use_path_binary);
+    // A cached path found on PATH mustn't be reused once PATH is off.
+    assert_ne!(
+      settings.resolution_key(),
+      MplsSettings::default().resolution_key()
+    );
+  }
+
+  #[test]
+  fn disabled_features_always_win() {
+    let settings = from_json(serde_json::json!({
+      "features": ["emoji", "footnotes"],
+      "disabled_features": ["emoji"],
+    }));
+    assert_eq!(settings.arguments(), ["--enable-footnotes"]);
+
+    let mut settings = from_json(serde_json::json!({ "disabled_features": ["emoji"] }));
+    assert_eq!(
+      settings.arguments(),
+      ["--enable-wikilinks", "--enable-footnotes"]
+    );
+    settings.binary_arguments = Some(vec![
+      "--enable-emoji".to_string(),
+      "--dark-mode".to_string(),
+    ]);
+    assert_eq!(settings.arguments(), ["--dark-mode"]);
+
+    // Nor can a profile turn it back on.
+    let settings = from_json(
+      with_profile(serde_json::json!({
+        "profiles": { "full": { "features": ["emoji", "wikilinks", "footnotes"] } },
+        "active_profile": "full",
+        "disabled_features": ["emoji"],
+      }))
+      .unwrap(),
+    );
+    assert_eq!(
+      settings.arguments(),
+      ["--enable-wikilinks", "--enable-footnotes"]
+    );
+  }
+
+  #[test]
+  fn asset_sha256_has_to_be_a_digest() {
+    let digest = "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD";
+    assert!(
+      from_json(serde_json::json!({ "asset_sha256": digest }))
+        .problems()
+        .is_empty()
+    );
+    for digest in [
+      "",
+      "ba7816bf",
+      &"g".repeat(64),
+      &format!("sha256:{}", digest),
+    ] {
+      let problems = from_json(serde_json::json!({ "asset_sha256": digest })).problems();
+      assert_eq!(problems.len(), 1, "{}", digest);
+    }
+  }
+
+  #[test]
+  fn release_tags_dont_need_a_version() {
+    for (tag, pinned_version) in [
+      ("v0.16.0-hotfix", "0.16.0-hotfix"),
+      ("mpls-v0.16.0", "0.16.0"),
+      ("nightly", "nightly"),
+    ] {
+      let settings = from_json(serde_json::json!({ "release_tag": tag, "version": "0.15.0" }));
+      assert!(settings.problems().is_empty(), "{}", tag);
+      assert_eq!(settings.pinned_tag().as_deref(), Some(tag));
+      assert_eq!(settings.pinned_version(), Some(pinned_version));
+    }
+  }
+
+  #[test]
+  fn exports_the_binary_object() {
+    assert_eq!(MplsSettings::default().binary_json(), None);
+    let settings = MplsSettings {
+      binary_arguments: Some(vec!["--enable-emoji".to_string()]),
+      binary_path: Some("/opt/mpls/bin/mpls".to_string()),
+      ..MplsSettings::default()
+    };
+    assert_eq!(
+      settings.binary_json(),
+      Some(serde_json::json!({
+        "arguments": ["--enable-emoji"],
+        "path": "/opt/mpls/bin/mpls",
+      }))
+    );
+  }
+}
@@ -0,0 +1,52 @@
+use regex::Regex;
+pub(crate) use semver::Version;
+use zed_extension_api as zed;
+
+// How a version appears in asset and directory names. Pre-release suffixes, like the date and
+// commit of a nightly (0.17.0-nightly.20250101.abc1234), are allowed; build metadata isn't.
+pub(crate) const VERSION_PATTERN: &str = r"[0-9]+\.[0-9]+\.[0-9]+(?:-[0-9A-Za-z.-]+)?";
+
+// The version a release tag stands for in asset names: the first thing in it that looks like one.
+// v0.16.0, v0.16.0+build.5, mpls-v0.16.0 and release-0.16.0 all give 0.16.0. Build metadata never
+// makes it into asset names, and VERSION_PATTERN stops short of it.
+pub(crate) fn asset_version(tag: &str) -> zed::Result<&str> {
+  // VERSION_PATTERN is a valid regex.
+  let version = Regex::new(VERSION_PATTERN)
+    .unwrap()
+    .find(tag)
+    .map(|version| version.as_str())
+    .filter(|version| Version::parse(version).is_ok())
+    .ok_or_else(|| {
+      format!(
+        "Can't find a version like 0.16.0 in the release tag \"{}\".",
+        tag
+      )
+    })?;
+  Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn finds_the_version_anywhere_in_the_tag() {
+    assert_eq!(asset_version("v0.16.0"), Ok("0.16.0"));
+    assert_eq!(asset_version("mpls-v0.16.0"), Ok("0.16.0"));
+    assert_eq!(asset_version("release-0.16.0"), Ok("0.16.0"));
+  }
+
+  #[test]
+  fn drops_build_metadata() {
+    assert_eq!(asset_version("v0.16.0+build.5"), Ok("0.16.0"));
+    // Pre-release suffixes are part of asset names, so they stay.
+    assert_eq!(asset_version("v0.17.0-rc.1+build.5"), Ok("0.17.0-rc.1"));
+  }
+
+  #[test]
+  fn rejects_tags_without_a_version() {
+    let err = asset_version("nightly").unwrap_err();
+    assert!(err.contains("\"nightly\""), "{}", err);
+    assert!(asset_version("v0.16").is_err());
+  }
+}